@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    configs::{self, AbuseConfig},
+    db,
+    enums::Status,
+    structs::{IpSightings, UidSightings},
+    utils,
+};
+
+// 自动标记灰名单时记录的操作者
+const ABUSE_ROLE: &str = "abuse";
+
+/// 数据库中只保存access_key的SHA-256
+pub fn hash_key(key: &str) -> String {
+    utils::to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// 统计的起始时间(毫秒) 更早的记录会被清理
+fn since(config: &AbuseConfig) -> i64 {
+    utils::current_milliseconds() - config.retention_secs as i64 * 1000
+}
+
+pub fn is_suspicious(config: &AbuseConfig, s: &UidSightings) -> bool {
+    s.keys >= config.max_keys as i64 || s.ips >= config.max_ips as i64
+}
+
+/// 记录一次通过access_key的查询 开启autoGray时将可疑的normal用户标记为灰名单
+pub async fn record(key: String, uid: i64, ip: String) {
+    let config = configs::get();
+    let config = &config.abuse;
+
+    if !config.enabled || !db::record_sighting(&hash_key(&key), uid, &ip).await {
+        return;
+    }
+
+    if !config.auto_gray {
+        return;
+    }
+
+    let s = db::count_sightings(uid, since(config)).await;
+
+    // 不覆盖管理员设置的黑白名单
+    if !is_suspicious(config, &s) || db::get_user_by_id(uid).await.status != Status::None {
+        return;
+    }
+
+    let reason = format!("自动标记: 近期使用了{}个access_key、{}个IP", s.keys, s.ips);
+
+    if db::do_op(uid, &Status::Gray, ABUSE_ROLE, &reason).await {
+        warn!("Auto graylisted {uid}: {reason}");
+    }
+}
+
+/// 可疑的uid和IP 阈值为None时使用配置中的值
+pub async fn report(
+    min_keys: Option<i64>,
+    min_ips: Option<i64>,
+    min_uids: Option<i64>,
+    limit: i64,
+) -> (Vec<UidSightings>, Vec<IpSightings>) {
+    let config = configs::get();
+    let config = &config.abuse;
+    let since = since(config);
+
+    let uids = db::list_uid_sightings(
+        since,
+        min_keys.unwrap_or(config.max_keys as i64),
+        min_ips.unwrap_or(config.max_ips as i64),
+        limit,
+    )
+    .await;
+
+    let ips = db::list_ip_sightings(
+        since,
+        min_uids.unwrap_or(config.max_uids_per_ip as i64),
+        limit,
+    )
+    .await;
+
+    (uids, ips)
+}
+
+pub async fn run_cleanup() {
+    let secs = configs::get().abuse.cleanup_interval_secs;
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+
+    loop {
+        interval.tick().await;
+
+        let config = configs::get();
+        let purged = db::purge_sightings(since(&config.abuse)).await;
+
+        if purged > 0 {
+            info!("Purged {purged} expired key sightings");
+        }
+    }
+}
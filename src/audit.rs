@@ -0,0 +1,61 @@
+use log::warn;
+
+use crate::{
+    db,
+    enums::AuditResult,
+    structs::{AdminKey, AuditEntry},
+    utils,
+};
+
+// 命令行操作记录的来源和role
+const LOCAL_IP: &str = "local";
+const LOCAL_ROLE: &str = "cli";
+
+/// 写入一条审计日志 key为None表示使用了不存在的key
+pub async fn record(
+    key: Option<&AdminKey>,
+    ip: &str,
+    action: &str,
+    target: &str,
+    result: AuditResult,
+) {
+    if result == AuditResult::Denied {
+        warn!("Denied {action}({target}) from {ip}");
+    }
+
+    let entry = AuditEntry {
+        id: 0,
+        key_id: key.map(|k| k.id),
+        role: key.map(|k| k.role.clone()),
+        action: action.to_owned(),
+        target: target.to_owned(),
+        ip: ip.to_owned(),
+        result,
+        created_at: utils::current_milliseconds(),
+    };
+
+    db::insert_audit(&entry).await;
+}
+
+/// 记录brbs-admin直接对数据库进行的操作
+pub async fn record_local(action: &str, target: &str, result: AuditResult) {
+    let entry = AuditEntry {
+        id: 0,
+        key_id: None,
+        role: Some(LOCAL_ROLE.to_owned()),
+        action: action.to_owned(),
+        target: target.to_owned(),
+        ip: LOCAL_IP.to_owned(),
+        result,
+        created_at: utils::current_milliseconds(),
+    };
+
+    db::insert_audit(&entry).await;
+}
+
+pub fn result_of(success: bool) -> AuditResult {
+    match success {
+        true => AuditResult::Ok,
+        false => AuditResult::Failed,
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    fs::{self, File, TryLockError},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection, Row};
+
+use crate::{configs, db, utils};
+
+// 恢复前校验的表结构
+const REQUIRED_SCHEMA: [(&str, &[&str]); 7] = [
+    ("users", &["uid", "status", "last_reason"]),
+    (
+        "reasons",
+        &["id", "uid", "op", "op_role", "reason", "op_time"],
+    ),
+    ("keys", &["id", "admin_key", "key_hash", "lvl", "role"]),
+    (
+        "webhook_outbox",
+        &[
+            "id",
+            "subscription",
+            "url",
+            "payload",
+            "attempts",
+            "status",
+            "next_attempt",
+            "last_error",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "audit_log",
+        &["id", "key_id", "role", "action", "target", "ip", "result", "created_at"],
+    ),
+    ("profiles", &["uid", "name", "level", "first_seen", "last_seen"]),
+    ("key_sightings", &["key_hash", "uid", "ip", "last_seen"]),
+];
+
+// 上一个备份使用的时间戳
+static LAST_BACKUP: AtomicI64 = AtomicI64::new(0);
+
+/// 文件名中的时间戳单调递增 同一毫秒内的多次备份不会重名
+fn backup_name() -> String {
+    let now = utils::current_milliseconds();
+    let last = LAST_BACKUP
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(last.max(now - 1) + 1)
+        })
+        .unwrap_or_default();
+
+    format!("black-{}.db", last.max(now - 1) + 1)
+}
+
+fn lock_file() -> PathBuf {
+    PathBuf::from(format!("{}.lock", db::database_file().display()))
+}
+
+/// 服务器运行期间持有数据库旁的锁文件 进程退出时由系统释放
+pub fn lock_database() -> Result<File, String> {
+    let path = lock_file();
+
+    let file = File::create(&path)
+        .map_err(|e| format!("Cannot open lock file {} with error: {e}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(_) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(format!(
+            "Database {} is in use by a running server",
+            db::database_file().display()
+        )),
+        Err(TryLockError::Error(e)) => Err(format!(
+            "Cannot lock {} with error: {e}",
+            path.display()
+        )),
+    }
+}
+
+fn list_backups(dir: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(dir) => dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                name.starts_with("black-") && name.ends_with(".db")
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    // 文件名中带有时间戳 按名称排序即为按时间排序
+    files.sort();
+    files
+}
+
+fn apply_retention(dir: &str, retention: usize) {
+    let files = list_backups(dir);
+
+    if files.len() <= retention {
+        return;
+    }
+
+    for f in &files[..files.len() - retention] {
+        match fs::remove_file(f) {
+            Ok(_) => info!("Removed expired backup {}", f.display()),
+            Err(e) => warn!(
+                "Cannot remove expired backup {} with error: {e}",
+                f.display()
+            ),
+        }
+    }
+}
+
+/// 通过`VACUUM INTO`生成一致的数据库快照 返回备份文件路径
+pub async fn backup() -> Option<PathBuf> {
+    let config = &configs::get().backup;
+
+    if let Err(e) = fs::create_dir_all(&config.dir) {
+        error!("Cannot create backup dir {} with error: {e}", config.dir);
+        return None;
+    }
+
+    let path = Path::new(&config.dir).join(backup_name());
+
+    if !db::vacuum_into(path.to_str()?).await {
+        return None;
+    }
+
+    apply_retention(&config.dir, config.retention);
+
+    Some(path)
+}
+
+pub async fn run_scheduler() {
+    let config = configs::get();
+    let secs = config.backup.interval_secs;
+
+    // postgres请使用pg_dump备份
+    if secs == 0 || !config.database.is_sqlite() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+
+    // 第一次tick会立即完成 跳过启动时的备份
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        backup().await;
+    }
+}
+
+async fn validate_schema(path: &Path) -> Result<(), String> {
+    let url = format!("sqlite:{}", path.display());
+
+    let mut conn = SqliteConnectOptions::from_str(&url)
+        .map_err(|e| e.to_string())?
+        .connect()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let ret: String = sqlx::query("PRAGMA integrity_check")
+        .fetch_one(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .get(0);
+
+    if ret != "ok" {
+        return Err(format!("integrity check failed: {ret}"));
+    }
+
+    for (table, columns) in REQUIRED_SCHEMA {
+        let rows = sqlx::query(&format!("PRAGMA table_info({table})"))
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if rows.is_empty() {
+            return Err(format!("missing table {table}"));
+        }
+
+        let names: Vec<String> = rows.iter().map(|r| r.get("name")).collect();
+
+        for col in columns {
+            if !names.iter().any(|n| n == col) {
+                return Err(format!("missing column {table}.{col}"));
+            }
+        }
+    }
+
+    conn.close().await.map_err(|e| e.to_string())
+}
+
+/// 校验备份文件后替换当前数据库 服务器运行时拒绝执行
+pub async fn restore(from: &str) -> bool {
+    let from = Path::new(from);
+
+    // 恢复期间持有锁 避免服务器同时启动
+    let _lock = match lock_database() {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("{e}");
+            return false;
+        }
+    };
+
+    if let Err(e) = validate_schema(from).await {
+        error!("Backup {} is invalid: {e}", from.display());
+        return false;
+    }
+
+    let target = db::database_file();
+
+    // 先复制到同目录的临时文件 复制失败时当前数据库保持不变
+    let tmp = PathBuf::from(format!("{}.restore-tmp", target.display()));
+
+    let copied = fs::copy(from, &tmp).and_then(|_| File::open(&tmp)?.sync_all());
+
+    if let Err(e) = copied {
+        error!(
+            "Cannot copy backup {} to {} with error: {e}",
+            from.display(),
+            tmp.display()
+        );
+        let _ = fs::remove_file(&tmp);
+        return false;
+    }
+
+    if target.exists() {
+        let saved = format!(
+            "{}.pre-restore-{}",
+            target.display(),
+            utils::current_milliseconds()
+        );
+
+        // 日志文件随旧数据库一起移走 避免污染恢复后的数据
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let src = PathBuf::from(format!("{}{suffix}", target.display()));
+            if !src.exists() {
+                continue;
+            }
+            let dst = format!("{saved}{suffix}");
+            if let Err(e) = fs::rename(&src, &dst) {
+                error!("Cannot move {} to {dst} with error: {e}", src.display());
+                let _ = fs::remove_file(&tmp);
+                return false;
+            }
+        }
+
+        info!("Current database saved as {saved}");
+    }
+
+    match fs::rename(&tmp, &target) {
+        Ok(_) => {
+            info!("Successfully restored database from {}", from.display());
+            true
+        }
+        Err(e) => {
+            error!(
+                "Cannot move {} to {} with error: {e}",
+                tmp.display(),
+                target.display()
+            );
+            false
+        }
+    }
+}
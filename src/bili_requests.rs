@@ -1,22 +1,306 @@
-use crate::utils::{current_milliseconds, get_response_json};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
 
-const APP_KEY: &str = "1d8b6e7d45233436";
-const APP_SEC: &str = "560c52ccd288fed045859ed18bffd973";
+use async_trait::async_trait;
+use json::JsonValue;
+use log::{error, info, warn};
+use reqwest::Url;
 
-pub async fn get_uid_by_access_key(key: &str) -> Option<i64> {
-    let ts = current_milliseconds() / 1000;
-    let sign_str = format!("access_key={key}&appkey={APP_KEY}&client=android&ts={ts}{APP_SEC}");
-    let sign = format!("{:x}", md5::compute(&sign_str));
+use crate::{
+    configs::{BiliApp, BiliConfig},
+    enums::BiliError,
+    structs::BiliProfile,
+    utils::{current_milliseconds, get_response_json},
+};
 
-    let url = format!("https://app.bilibili.com/x/v2/account/myinfo?access_key={key}&appkey={APP_KEY}&ts={ts}&client=android&sign={sign}");
+// 重试的初始间隔(毫秒) 之后每次翻倍
+const RETRY_BASE_DELAY: u64 = 200;
 
-    let ret = reqwest::get(url).await.ok()?;
+// 未登录、access_key错误、access_key过期
+const INVALID_KEY_CODES: [i64; 3] = [-101, -2, -658];
 
-    let ret = ret.bytes().await.ok()?;
-    let json = get_response_json(ret);
+/// 查询B站用户信息 通过actix的app data注入 测试时可替换为FakeBiliClient
+#[async_trait]
+pub trait BiliClient: Send + Sync {
+    /// app为配置中的名称 为None时按顺序尝试全部app 不存在时返回UnknownApp
+    async fn get_profile_by_access_key(&self, key: &str, app: Option<&str>) -> Result<BiliProfile, BiliError>;
 
-    match json {
-        Some(json) => json["data"]["mid"].as_i64(),
-        _ => None,
+    /// 重新加载配置时调用 进行中的请求继续使用原配置
+    fn reload(&self, _config: &BiliConfig) {}
+}
+
+// 熔断结束后连续失败次数仍保持在阈值以上(半开) 只放行一个探测请求
+// 探测成功后恢复 失败则立即重新熔断
+#[derive(Default)]
+struct Breaker {
+    // 连续不可用的次数
+    failures: u32,
+    // 熔断结束的时间(毫秒)
+    open_until: i64,
+}
+
+// 每个app单独熔断 国际版不可用时不影响国内版
+struct AppClient {
+    app: BiliApp,
+    breaker: Mutex<Breaker>,
+}
+
+// 重新加载配置时整体替换 熔断状态随之重置
+struct ClientState {
+    client: reqwest::Client,
+    config: BiliConfig,
+    apps: Vec<AppClient>,
+}
+
+pub struct HttpBiliClient {
+    state: RwLock<Arc<ClientState>>,
+}
+
+// 依次尝试多个app时返回最有参考价值的错误 任一app不可用时key可能仍然有效
+fn severity(e: &BiliError) -> u8 {
+    match e {
+        BiliError::InvalidKey | BiliError::UnknownApp => 0,
+        BiliError::Rejected(_) => 1,
+        BiliError::Unavailable => 2,
+    }
+}
+
+// 国际版的用户名字段为uname
+fn profile_from_json(data: &JsonValue) -> Option<BiliProfile> {
+    Some(BiliProfile {
+        uid: data["mid"].as_i64()?,
+        name: data["name"].as_str().or(data["uname"].as_str()).unwrap_or_default().to_owned(),
+        level: data["level"].as_i32().unwrap_or(0),
+        vip: data["vip"]["status"].as_i64() == Some(1),
+        face: data["face"].as_str().unwrap_or_default().to_owned(),
+    })
+}
+
+impl ClientState {
+    fn new(config: &BiliConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .unwrap();
+
+        let apps = config
+            .apps
+            .iter()
+            .map(|app| AppClient {
+                app: app.clone(),
+                breaker: Mutex::new(Breaker::default()),
+            })
+            .collect();
+
+        ClientState {
+            client,
+            config: config.clone(),
+            apps,
+        }
+    }
+
+    fn cooldown_until(&self) -> i64 {
+        current_milliseconds() + self.config.breaker_cooldown_secs as i64 * 1000
+    }
+
+    fn breaker_open(&self, app: &AppClient) -> bool {
+        let mut breaker = app.breaker.lock().unwrap();
+
+        if breaker.open_until > current_milliseconds() {
+            return true;
+        }
+
+        // 半开时放行当前请求作为探测 结果返回前其他请求仍被熔断
+        let threshold = self.config.breaker_threshold;
+        if threshold > 0 && breaker.failures >= threshold {
+            breaker.open_until = self.cooldown_until();
+        }
+
+        false
+    }
+
+    fn record(&self, app: &AppClient, available: bool) {
+        let mut breaker = app.breaker.lock().unwrap();
+
+        if available {
+            breaker.failures = 0;
+            breaker.open_until = 0;
+            return;
+        }
+
+        breaker.failures += 1;
+
+        let threshold = self.config.breaker_threshold;
+        if threshold > 0 && breaker.failures >= threshold {
+            let cooldown = self.config.breaker_cooldown_secs;
+            warn!(
+                "Bilibili api of {} unavailable {} times in a row, circuit open for {cooldown}s",
+                app.app.name, breaker.failures
+            );
+            breaker.open_until = self.cooldown_until();
+        }
+    }
+
+    /// 只有返回Unavailable时才需要重试
+    async fn fetch(&self, url: &Url) -> Result<JsonValue, (BiliError, String)> {
+        let unavailable = |e: String| (BiliError::Unavailable, e);
+
+        // 错误信息中的url包含access_key
+        let ret = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| unavailable(e.without_url().to_string()))?;
+
+        let status = ret.status();
+        if status.is_server_error() {
+            return Err(unavailable(format!("unexpected status {status}")));
+        }
+        if !status.is_success() {
+            return Err((BiliError::Rejected(status.as_u16() as i64), format!("unexpected status {status}")));
+        }
+
+        let body = ret.bytes().await.map_err(|e| unavailable(e.without_url().to_string()))?;
+
+        get_response_json(body).ok_or(unavailable("invalid json".to_owned()))
+    }
+
+    async fn query(&self, app: &AppClient, key: &str) -> Result<BiliProfile, BiliError> {
+        if self.breaker_open(app) {
+            return Err(BiliError::Unavailable);
+        }
+
+        let BiliApp {
+            name,
+            api,
+            base_url,
+            app_key,
+            app_sec,
+            client,
+        } = &app.app;
+
+        let mut url = match Url::parse(&format!("{base_url}{}", api.myinfo_path())) {
+            Ok(url) => url,
+            Err(e) => {
+                error!("Invalid myinfo url of {name}: {e}");
+                return Err(BiliError::Unavailable);
+            }
+        };
+
+        // 参数按名称排序后签名 签名使用编码后的参数
+        let ts = current_milliseconds() / 1000;
+        url.query_pairs_mut()
+            .append_pair("access_key", key)
+            .append_pair("appkey", app_key)
+            .append_pair("client", client)
+            .append_pair("ts", &ts.to_string());
+
+        let sign_str = format!("{}{app_sec}", url.query().unwrap_or_default());
+        let sign = format!("{:x}", md5::compute(&sign_str));
+        url.query_pairs_mut().append_pair("sign", &sign);
+
+        let mut attempt = 0;
+
+        let json = loop {
+            match self.fetch(&url).await {
+                Ok(json) => break json,
+                Err((BiliError::Unavailable, e)) if attempt < self.config.max_retries => {
+                    let delay = RETRY_BASE_DELAY << attempt;
+                    warn!("Cannot request myinfo of {name} (attempt {}), retry in {delay}ms: {e}", attempt + 1);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    attempt += 1;
+                }
+                Err((err, e)) => {
+                    error!("Cannot request myinfo of {name} with error: {e}");
+                    self.record(app, err != BiliError::Unavailable);
+                    return Err(err);
+                }
+            }
+        };
+
+        self.record(app, true);
+
+        match json["code"].as_i64() {
+            Some(0) => profile_from_json(&json["data"]).ok_or(BiliError::InvalidKey),
+            Some(code) if INVALID_KEY_CODES.contains(&code) => Err(BiliError::InvalidKey),
+            Some(code) => {
+                warn!("Myinfo of {name} rejected with code {code}: {}", json["message"]);
+                Err(BiliError::Rejected(code))
+            }
+            None => Err(BiliError::Unavailable),
+        }
+    }
+}
+
+impl HttpBiliClient {
+    pub fn new(config: &BiliConfig) -> Self {
+        HttpBiliClient {
+            state: RwLock::new(Arc::new(ClientState::new(config))),
+        }
+    }
+}
+
+#[async_trait]
+impl BiliClient for HttpBiliClient {
+    async fn get_profile_by_access_key(&self, key: &str, app: Option<&str>) -> Result<BiliProfile, BiliError> {
+        let state = self.state.read().unwrap().clone();
+
+        let apps: Vec<&AppClient> = match app {
+            Some(name) => match state.apps.iter().find(|a| a.app.name == name) {
+                Some(app) => vec![app],
+                None => return Err(BiliError::UnknownApp),
+            },
+            None => state.apps.iter().collect(),
+        };
+
+        let mut err = None;
+
+        for app in apps {
+            match state.query(app, key).await {
+                Ok(profile) => return Ok(profile),
+                Err(e) if err.as_ref().is_none_or(|err| severity(&e) > severity(err)) => err = Some(e),
+                Err(_) => {}
+            }
+        }
+
+        Err(err.unwrap_or(BiliError::Unavailable))
+    }
+
+    fn reload(&self, config: &BiliConfig) {
+        *self.state.write().unwrap() = Arc::new(ClientState::new(config));
+        info!("Reloaded {} bili apps", config.apps.len());
+    }
+}
+
+/// 不访问网络 只认识预先设置的access_key
+#[derive(Default)]
+pub struct FakeBiliClient {
+    profiles: HashMap<String, BiliProfile>,
+}
+
+impl FakeBiliClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(self, key: &str, uid: i64) -> Self {
+        self.with_profile(key, BiliProfile { uid, ..Default::default() })
+    }
+
+    pub fn with_profile(mut self, key: &str, profile: BiliProfile) -> Self {
+        self.profiles.insert(key.to_owned(), profile);
+        self
+    }
+}
+
+#[async_trait]
+impl BiliClient for FakeBiliClient {
+    async fn get_profile_by_access_key(&self, key: &str, _app: Option<&str>) -> Result<BiliProfile, BiliError> {
+        self.profiles.get(key).cloned().ok_or(BiliError::InvalidKey)
     }
 }
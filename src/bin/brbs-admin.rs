@@ -0,0 +1,395 @@
+use std::{fs, process};
+
+use json::object;
+use log::error;
+
+use brbs_rs::{
+    audit, configs, db,
+    enums::{AuditResult, RevokeError, Status},
+    structs::AdminKey,
+    utils,
+};
+
+const USAGE: &str = "Usage: brbs-admin <command>
+
+Commands:
+  keys list                          List all admin keys
+  keys gen <lvl> <role>              Generate an admin key
+  keys revoke <key>                  Revoke an admin key
+  keys revoke --role <role>          Revoke all admin keys of a role except owner
+  keys regen-owner [id]              Regenerate an owner key, id is required if there are several
+  keys recover-owner [--revoke-others]
+                                     Generate a new owner key, optionally revoke all other owners
+  user get <uid>                     Show the status of a user
+  user set <uid> <status> <reason>   Set the status (normal/black/white/gray) of a user
+  user history <uid> [limit]         Show the status changes of a user
+  stats                              Show statistics
+  export <file>                      Export all black/white/gray users to a json file
+  import <file>                      Import users from a json file created by export
+  migrate                            Create or upgrade the database tables
+
+The database is read from config.json or the file given by BRBS_CONFIG.";
+
+const COMMANDS: [&str; 6] = ["keys", "user", "stats", "export", "import", "migrate"];
+
+// 通过命令行修改状态时记录的操作者
+const CLI_ROLE: &str = "cli";
+const IMPORT_ROLE: &str = "import";
+
+// history默认显示的记录数
+const DEFAULT_HISTORY_LIMIT: i64 = 20;
+
+fn parse_uid(s: &str) -> Result<i64, String> {
+    s.parse().map_err(|_| format!("invalid uid: {s}"))
+}
+
+fn parse_status(s: &str) -> Result<Status, String> {
+    Status::from_name(s).ok_or(format!("invalid status: {s}, expected normal/black/white/gray"))
+}
+
+async fn keys_list() -> Result<(), String> {
+    println!("{:<6}{:<36}{:<6}role", "id", "key", "lvl");
+
+    for k in db::list_admin_keys().await {
+        println!("{:<6}{:<36}{:<6}{}", k.id, k.key, k.lvl, k.role);
+    }
+
+    Ok(())
+}
+
+async fn keys_gen(lvl: &str, role: &str) -> Result<(), String> {
+    let lvl: i8 = lvl.parse().map_err(|_| format!("invalid lvl: {lvl}"))?;
+
+    // 与HTTP接口的规则相同
+    if !db::valid_key_lvl(role, lvl) {
+        return Err("lvl of owner must be 127, lvl of other roles must be between 0 and 127".to_owned());
+    }
+
+    let ret = db::gen_key(lvl, role).await;
+    audit::record_local(
+        "key.gen",
+        &format!("role={role} lvl={lvl}"),
+        audit::result_of(ret.is_some()),
+    )
+    .await;
+
+    let key = ret.ok_or("cannot generate admin key")?;
+
+    println!("{key}");
+    Ok(())
+}
+
+async fn keys_revoke(key: &str) -> Result<(), String> {
+    let revoked = db::get_admin_key(key)
+        .await
+        .ok_or(format!("admin key not found: {key}"))?;
+
+    let ret = db::revoke_admin_key(revoked.id).await;
+    let result = match &ret {
+        Ok(()) => AuditResult::Ok,
+        Err(RevokeError::Failed) => AuditResult::Failed,
+        Err(_) => AuditResult::Denied,
+    };
+    audit::record_local("key.revoke", &format!("key#{}", revoked.id), result).await;
+
+    match ret {
+        Ok(()) => (),
+        Err(RevokeError::NotFound) => return Err(format!("admin key not found: {key}")),
+        Err(RevokeError::LastOwner) => {
+            return Err("the last owner key cannot be revoked, use keys regen-owner instead".to_owned())
+        }
+        Err(RevokeError::Failed) => return Err(format!("cannot revoke {key}")),
+    }
+
+    println!("Revoked {key}");
+    Ok(())
+}
+
+async fn keys_revoke_role(role: &str) -> Result<(), String> {
+    if role == db::OWNER_ROLE {
+        return Err("owner keys can only be revoked one by one".to_owned());
+    }
+
+    let ok = db::revoke_admin_key_by_role(role).await;
+    audit::record_local("key.revoke", &format!("role={role}"), audit::result_of(ok)).await;
+
+    if !ok {
+        return Err(format!("cannot revoke admin keys of {role}"));
+    }
+
+    println!("Revoked all admin keys of {role}");
+    Ok(())
+}
+
+async fn keys_regen_owner(id: Option<&str>) -> Result<(), String> {
+    let id: Option<i64> = match id {
+        Some(s) => Some(s.parse().map_err(|_| format!("invalid id: {s}"))?),
+        None => None,
+    };
+
+    let owners: Vec<AdminKey> = db::list_admin_keys()
+        .await
+        .into_iter()
+        .filter(|k| k.role == db::OWNER_ROLE && id.is_none_or(|id| id == k.id))
+        .collect();
+
+    let owner = match owners.as_slice() {
+        [owner] => owner,
+        [] => return Err("owner key not found, run migrate or keys recover-owner first".to_owned()),
+        _ => return Err("there are several owner keys, specify the id to regenerate".to_owned()),
+    };
+
+    let ret = db::regen_owner_key(&owner.key).await;
+    audit::record_local(
+        "owner.regen",
+        &format!("key#{}", owner.id),
+        audit::result_of(ret.is_some()),
+    )
+    .await;
+
+    let key = ret.ok_or("cannot regenerate owner key")?;
+
+    println!("{key}");
+    Ok(())
+}
+
+/// 丢失全部owner key时 通过本地数据库访问权限恢复
+async fn keys_recover_owner(revoke_others: bool) -> Result<(), String> {
+    let others: Vec<AdminKey> = db::list_admin_keys()
+        .await
+        .into_iter()
+        .filter(|k| k.role == db::OWNER_ROLE)
+        .collect();
+
+    let ret = db::gen_key(127, db::OWNER_ROLE).await;
+    audit::record_local(
+        "owner.recover",
+        &format!("role={} lvl=127", db::OWNER_ROLE),
+        audit::result_of(ret.is_some()),
+    )
+    .await;
+
+    let key = ret.ok_or("cannot generate owner key")?;
+
+    if revoke_others {
+        for k in others {
+            let ok = db::revoke_admin_key(k.id).await.is_ok();
+            audit::record_local("key.revoke", &format!("key#{}", k.id), audit::result_of(ok)).await;
+
+            if !ok {
+                return Err(format!("new owner key {key} generated, but cannot revoke key#{}", k.id));
+            }
+        }
+    }
+
+    println!("{key}");
+    Ok(())
+}
+
+async fn user_get(uid: &str) -> Result<(), String> {
+    let user = db::get_user_by_id(parse_uid(uid)?).await;
+
+    println!("uid:    {}", user.uid);
+    println!("status: {}", user.status.display());
+    println!("reason: {}", user.last_reason.as_deref().unwrap_or("无"));
+
+    // 只有通过access_key查询过的用户才有记录
+    if let Some(p) = db::get_profile(user.uid).await {
+        println!("name:   {} (lv{})", p.name, p.level);
+        println!("seen:   {} - {}", p.first_seen, p.last_seen);
+    }
+
+    Ok(())
+}
+
+async fn user_set(uid: &str, status: &str, reason: &str) -> Result<(), String> {
+    let uid = parse_uid(uid)?;
+    let status = parse_status(status)?;
+
+    let ok = db::do_op(uid, &status, CLI_ROLE, reason).await;
+    audit::record_local(
+        &format!("user.{}", status.display()),
+        &uid.to_string(),
+        audit::result_of(ok),
+    )
+    .await;
+
+    if !ok {
+        return Err(format!("cannot set status of {uid}"));
+    }
+
+    println!("{uid} -> {}", status.display());
+    Ok(())
+}
+
+async fn user_history(uid: &str, limit: Option<&str>) -> Result<(), String> {
+    let uid = parse_uid(uid)?;
+    let limit = match limit {
+        Some(s) => s.parse().map_err(|_| format!("invalid limit: {s}"))?,
+        None => DEFAULT_HISTORY_LIMIT,
+    };
+
+    for r in db::get_reasons_by_uid(uid, limit).await {
+        println!(
+            "#{:<6} {} {:<7} by {:<8} {}",
+            r.id,
+            r.op_time,
+            r.op.display(),
+            r.op_role,
+            r.reason
+        );
+    }
+
+    Ok(())
+}
+
+async fn stats() -> Result<(), String> {
+    println!("black: {}", db::count_total_by_status(&Status::Black).await);
+    println!("white: {}", db::count_total_by_status(&Status::White).await);
+    println!("keys:  {}", db::list_admin_keys().await.len());
+    println!("last change: {}", db::get_last_reason_id().await);
+    Ok(())
+}
+
+/* 导出格式
+{
+  "version": 1,
+  "exportedAt": 1653490177054,
+  "users": [{"uid": 123456, "status": "black", "reason": "..."}]
+}
+*/
+async fn export(file: &str) -> Result<(), String> {
+    let mut users = Vec::new();
+
+    for (uid, _) in db::get_marked_users().await {
+        let user = db::get_user_by_id(uid).await;
+
+        users.push(object! {
+            uid: user.uid,
+            status: user.status.display(),
+            reason: user.last_reason
+        });
+    }
+
+    let count = users.len();
+    let body = object! {
+        version: 1,
+        exportedAt: utils::current_milliseconds(),
+        users: users
+    };
+
+    fs::write(file, body.pretty(2)).map_err(|e| format!("cannot write {file}: {e}"))?;
+
+    println!("Exported {count} users to {file}");
+    Ok(())
+}
+
+async fn import(file: &str) -> Result<(), String> {
+    let s = fs::read_to_string(file).map_err(|e| format!("cannot read {file}: {e}"))?;
+    let json = json::parse(&s).map_err(|e| format!("cannot parse {file}: {e}"))?;
+
+    if !json["users"].is_array() {
+        return Err(format!("{file} is not an export file"));
+    }
+
+    // skipped为状态未变化的用户 failed为无效条目和写入失败的用户
+    let (mut imported, mut skipped, mut failed) = (0, 0, 0);
+
+    for (i, u) in json["users"].members().enumerate() {
+        let (uid, status) = match (
+            u["uid"].as_i64(),
+            u["status"].as_str().and_then(Status::from_name),
+        ) {
+            (Some(uid), Some(status)) => (uid, status),
+            _ => {
+                error!("Skip invalid entry #{i}: {u}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        // 状态相同时不重复写入记录
+        if db::get_user_by_id(uid).await.status == status {
+            skipped += 1;
+            continue;
+        }
+
+        let reason = u["reason"].as_str().unwrap_or("无");
+
+        if db::do_op(uid, &status, IMPORT_ROLE, reason).await {
+            imported += 1;
+        } else {
+            error!("Cannot import uid {uid}");
+            failed += 1;
+        }
+    }
+
+    audit::record_local("user.import", file, audit::result_of(failed == 0)).await;
+
+    println!("Imported {imported} users, skipped {skipped} unchanged, {failed} failed");
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} entries of {file} were not imported")),
+    }
+}
+
+async fn migrate() -> Result<(), String> {
+    // 日志级别为warn 新生成的owner key需要直接打印
+    if let Some(key) = db::prepare().await {
+        println!("Generated owner key: {key}");
+    }
+
+    println!("Database is up to date");
+    Ok(())
+}
+
+async fn run(args: &[&str]) -> Result<(), String> {
+    match args {
+        ["keys", "list"] => keys_list().await,
+        ["keys", "gen", lvl, role] => keys_gen(lvl, role).await,
+        ["keys", "revoke", "--role", role] => keys_revoke_role(role).await,
+        ["keys", "revoke", key] => keys_revoke(key).await,
+        ["keys", "regen-owner"] => keys_regen_owner(None).await,
+        ["keys", "regen-owner", id] => keys_regen_owner(Some(id)).await,
+        ["keys", "recover-owner"] => keys_recover_owner(false).await,
+        ["keys", "recover-owner", "--revoke-others"] => keys_recover_owner(true).await,
+        ["user", "get", uid] => user_get(uid).await,
+        ["user", "set", uid, status, reason] => user_set(uid, status, reason).await,
+        ["user", "history", uid] => user_history(uid, None).await,
+        ["user", "history", uid, limit] => user_history(uid, Some(limit)).await,
+        ["stats"] => stats().await,
+        ["export", file] => export(file).await,
+        ["import", file] => import(file).await,
+        ["migrate"] => migrate().await,
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // 只输出警告和错误 命令结果直接打印到stdout
+    flexi_logger::Logger::try_with_env_or_str("warn")
+        .unwrap()
+        .start()
+        .unwrap();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+
+    let ret = match configs::init() {
+        Ok(_) => {
+            // 未执行过migrate的数据库也能直接使用 不会生成owner
+            if args.first().is_some_and(|c| COMMANDS.contains(c)) {
+                db::migrate().await;
+            }
+            run(&args).await
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = ret {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
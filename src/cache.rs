@@ -0,0 +1,98 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::{configs, structs::User};
+
+lazy_static::lazy_static! {
+    static ref CACHE: Mutex<LruCache<i64, (User, Instant)>> = {
+        Mutex::new(LruCache::new(configs::get().cache.capacity))
+    };
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+fn enabled() -> bool {
+    configs::get().cache.capacity > 0
+}
+
+pub fn get(uid: i64) -> Option<User> {
+    if !enabled() {
+        return None;
+    }
+
+    let ttl = Duration::from_secs(configs::get().cache.ttl_secs);
+    let mut cache = CACHE.lock().unwrap();
+
+    let hit = match cache.get(&uid) {
+        Some((user, at)) if at.elapsed() < ttl => Some(user.clone()),
+        Some(_) => {
+            cache.pop(&uid);
+            None
+        }
+        None => None,
+    };
+
+    match hit {
+        Some(_) => HITS.fetch_add(1, Ordering::Relaxed),
+        None => MISSES.fetch_add(1, Ordering::Relaxed),
+    };
+
+    hit
+}
+
+/// 写入操作使用 总是覆盖旧值
+pub fn put(user: User) {
+    if !enabled() {
+        return;
+    }
+
+    CACHE.lock().unwrap().put(user.uid, (user, Instant::now()));
+}
+
+pub fn remove(uid: i64) {
+    CACHE.lock().unwrap().pop(&uid);
+}
+
+/// 查询操作使用 已有缓存时不覆盖 避免并发写入后被旧值覆盖
+pub fn fill(user: User) {
+    if !enabled() {
+        return;
+    }
+
+    let mut cache = CACHE.lock().unwrap();
+
+    if !cache.contains(&user.uid) {
+        cache.put(user.uid, (user, Instant::now()));
+    }
+}
+
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+pub fn stats() -> CacheStats {
+    CacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        size: CACHE.lock().unwrap().len(),
+    }
+}
@@ -1,5 +1,743 @@
-// 服务器端口
-pub const SERVER_PORT: u16 = 7788;
+use std::{
+    fs,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 
-// 数据库地址
-pub const DATABASE_URL: &str = "sqlite:black.db";
\ No newline at end of file
+use json::JsonValue;
+use sqlx::{
+    postgres::PgConnectOptions,
+    sqlite::{SqliteJournalMode, SqliteSynchronous},
+};
+
+use crate::enums::{BiliApi, LogFormat};
+
+// 配置文件路径 可通过环境变量BRBS_CONFIG覆盖
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // 监听地址
+    pub host: String,
+    // 服务器端口 为0时不监听TCP
+    pub port: u16,
+    // Unix socket路径 为空时不监听
+    pub unix_socket: String,
+    // Unix socket文件的权限
+    pub unix_socket_mode: u32,
+    // 查询接口Cache-Control的max-age(秒) 为0时要求客户端每次通过ETag重新验证
+    pub max_age_secs: u64,
+    // 是否在/docs提供Swagger UI
+    pub swagger_ui: bool,
+    // 是否信任X-Forwarded-For/Forwarded头 仅在反向代理后部署时开启
+    pub trust_proxy: bool,
+    // 收到SIGTERM后等待处理中的请求和webhook投递的最长时间(秒)
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    // 数据库地址 sqlite:... 或 postgres://...
+    pub url: String,
+    // 日志模式: delete/truncate/persist/memory/wal/off
+    pub journal_mode: String,
+    // 同步级别: off/normal/full/extra
+    pub synchronous: String,
+    // 数据库被锁时的等待时间(毫秒)
+    pub busy_timeout_ms: u64,
+    pub min_connections: u32,
+    pub max_connections: u32,
+    // 每个连接缓存的预编译语句数量
+    pub statement_cache_capacity: usize,
+}
+
+impl DatabaseConfig {
+    pub fn is_sqlite(&self) -> bool {
+        self.url.starts_with("sqlite:")
+    }
+
+    pub fn is_postgres(&self) -> bool {
+        self.url.starts_with("postgres:") || self.url.starts_with("postgresql:")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    // 备份目录
+    pub dir: String,
+    // 定时备份间隔(秒) 为0时不进行定时备份
+    pub interval_secs: u64,
+    // 保留的备份数量
+    pub retention: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // 缓存的用户数量 为0时不使用缓存
+    pub capacity: usize,
+    // 缓存有效期(秒) 多实例部署时决定其他实例修改的最大可见延迟
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookSubscription {
+    // 订阅名称 投递记录通过名称关联订阅
+    pub name: String,
+    pub url: String,
+    // HMAC-SHA256签名密钥
+    pub secret: String,
+    // 订阅的事件: black/white/gray/none 为空时订阅全部
+    pub events: Vec<String>,
+}
+
+impl WebhookSubscription {
+    pub fn accepts(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub subscriptions: Vec<WebhookSubscription>,
+    // 最大投递次数 超过后标记为失败
+    pub max_attempts: u32,
+    // 重试间隔基数(秒) 每次失败后翻倍
+    pub retry_base_secs: u64,
+    // 扫描待投递记录的间隔(秒)
+    pub poll_interval_secs: u64,
+    // 单次投递超时(秒)
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    // 心跳间隔(秒)
+    pub heartbeat_secs: u64,
+    // 轮询数据库的间隔(秒) 用于获取其他实例写入的变更
+    pub poll_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    // 增量变更超过该数量时改为返回全量快照
+    pub max_changes: i64,
+    // 游标之后最早的变更超过该时间时改为返回全量快照
+    pub max_cursor_age_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    // Ed25519签名私钥文件 为空时不生成快照
+    pub signing_key_file: String,
+    // 生成快照的间隔(秒)
+    pub interval_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    // PEM格式的证书链和私钥 都不为空时TCP端口使用HTTPS
+    pub cert_file: String,
+    pub key_file: String,
+    // 检查证书文件是否更新的间隔(秒) 为0时只在收到SIGHUP时重新加载
+    pub watch_interval_secs: u64,
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        !self.cert_file.is_empty() && !self.key_file.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    // 同一IP连续使用错误key的次数达到该值后锁定 为0时不锁定
+    pub max_failures: u32,
+    // 首次锁定的时间(秒) 之后每次失败翻倍
+    pub lockout_secs: u64,
+    // 锁定时间上限(秒) 超过该时间没有失败时重新计数
+    pub max_lockout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AbuseConfig {
+    // 是否记录access_key、uid和来源IP的对应关系
+    pub enabled: bool,
+    // 记录保留时间(秒) 统计也只针对该时间内的记录
+    pub retention_secs: u64,
+    // 清理过期记录的间隔(秒)
+    pub cleanup_interval_secs: u64,
+    // 同一uid使用的access_key数量达到该值时标记为可疑
+    pub max_keys: u64,
+    // 同一uid的来源IP数量达到该值时标记为可疑
+    pub max_ips: u64,
+    // 同一IP查询的uid数量达到该值时标记为可疑
+    pub max_uids_per_ip: u64,
+    // 是否自动将可疑且状态为normal的uid标记为灰名单
+    pub auto_gray: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BiliApp {
+    // 请求时通过app参数指定 如android ios hd tv intl
+    pub name: String,
+    pub api: BiliApi,
+    // 接口地址 默认由api决定 测试时可指向本地模拟服务
+    pub base_url: String,
+    pub app_key: String,
+    pub app_sec: String,
+    // 请求参数中的client
+    pub client: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BiliConfig {
+    // 没有指定app时按顺序尝试
+    pub apps: Vec<BiliApp>,
+    // 建立连接的超时(秒)
+    pub connect_timeout_secs: u64,
+    // 整个请求的超时(秒)
+    pub timeout_secs: u64,
+    // 网络错误或5xx时的最大重试次数
+    pub max_retries: u32,
+    // 连续失败达到该次数后熔断 为0时不熔断
+    pub breaker_threshold: u32,
+    // 熔断持续时间(秒) 之后放行请求探测是否恢复
+    pub breaker_cooldown_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    // 日志级别 如info或info,brbs_rs=debug 环境变量RUST_LOG优先
+    pub level: String,
+    pub format: LogFormat,
+    // 日志文件目录 为空时只输出到stderr
+    pub dir: String,
+    // 单个文件超过该大小(MB)时轮转 为0时不按大小轮转
+    pub rotate_size_mb: u64,
+    // 是否每天轮转
+    pub rotate_daily: bool,
+    // 保留的历史日志文件数量 为0时全部保留
+    pub keep_files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub backup: BackupConfig,
+    pub cache: CacheConfig,
+    pub webhooks: WebhookConfig,
+    pub stream: StreamConfig,
+    pub sync: SyncConfig,
+    pub snapshot: SnapshotConfig,
+    pub tls: TlsConfig,
+    pub security: SecurityConfig,
+    pub bili: BiliConfig,
+    pub abuse: AbuseConfig,
+    pub log: LogConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: ServerConfig {
+                host: "127.0.0.1".to_owned(),
+                port: 7788,
+                unix_socket: String::new(),
+                unix_socket_mode: 0o660,
+                max_age_secs: 0,
+                swagger_ui: false,
+                trust_proxy: false,
+                shutdown_timeout_secs: 30,
+            },
+            database: DatabaseConfig {
+                url: "sqlite:black.db".to_owned(),
+                journal_mode: "wal".to_owned(),
+                synchronous: "normal".to_owned(),
+                busy_timeout_ms: 5000,
+                min_connections: 1,
+                max_connections: 10,
+                statement_cache_capacity: 100,
+            },
+            backup: BackupConfig {
+                dir: "backups".to_owned(),
+                interval_secs: 6 * 60 * 60,
+                retention: 7,
+            },
+            cache: CacheConfig {
+                capacity: 100000,
+                ttl_secs: 300,
+            },
+            webhooks: WebhookConfig {
+                subscriptions: Vec::new(),
+                max_attempts: 8,
+                retry_base_secs: 10,
+                poll_interval_secs: 5,
+                timeout_secs: 10,
+            },
+            stream: StreamConfig {
+                heartbeat_secs: 15,
+                poll_interval_secs: 5,
+            },
+            sync: SyncConfig {
+                max_changes: 10000,
+                max_cursor_age_secs: 7 * 24 * 3600,
+            },
+            snapshot: SnapshotConfig {
+                signing_key_file: String::new(),
+                interval_secs: 300,
+            },
+            tls: TlsConfig {
+                cert_file: String::new(),
+                key_file: String::new(),
+                watch_interval_secs: 60,
+            },
+            security: SecurityConfig {
+                max_failures: 5,
+                lockout_secs: 60,
+                max_lockout_secs: 60 * 60,
+            },
+            bili: BiliConfig {
+                apps: Vec::new(),
+                connect_timeout_secs: 3,
+                timeout_secs: 10,
+                max_retries: 2,
+                breaker_threshold: 5,
+                breaker_cooldown_secs: 30,
+            },
+            abuse: AbuseConfig {
+                enabled: true,
+                retention_secs: 7 * 24 * 60 * 60,
+                cleanup_interval_secs: 60 * 60,
+                max_keys: 5,
+                max_ips: 20,
+                max_uids_per_ip: 10,
+                auto_gray: false,
+            },
+            log: LogConfig {
+                level: "info".to_owned(),
+                format: LogFormat::Text,
+                dir: String::new(),
+                rotate_size_mb: 100,
+                rotate_daily: true,
+                keep_files: 7,
+            },
+        }
+    }
+}
+
+// 超出目标类型范围时报错 不截断
+fn read_uint<T: TryFrom<u64>>(json: &JsonValue, default: T) -> Result<T, String> {
+    match json {
+        JsonValue::Null => Ok(default),
+        v => {
+            let n = v.as_u64().ok_or(format!("expect unsigned integer but got {v}"))?;
+            T::try_from(n).map_err(|_| format!("{n} is out of range"))
+        }
+    }
+}
+
+fn read_bool(json: &JsonValue, default: bool) -> Result<bool, String> {
+    match json {
+        JsonValue::Null => Ok(default),
+        v => v.as_bool().ok_or(format!("expect boolean but got {v}")),
+    }
+}
+
+fn read_str(json: &JsonValue, default: &str) -> Result<String, String> {
+    match json {
+        JsonValue::Null => Ok(default.to_owned()),
+        v => v
+            .as_str()
+            .map(|s| s.to_owned())
+            .ok_or(format!("expect string but got {v}")),
+    }
+}
+
+fn read_subscriptions(json: &JsonValue) -> Result<Vec<WebhookSubscription>, String> {
+    if json.is_null() {
+        return Ok(Vec::new());
+    }
+
+    if !json.is_array() {
+        return Err(format!("expect array but got {json}"));
+    }
+
+    json.members()
+        .map(|sub| {
+            let events = &sub["events"];
+            Ok(WebhookSubscription {
+                name: read_str(&sub["name"], "")?,
+                url: read_str(&sub["url"], "")?,
+                secret: read_str(&sub["secret"], "")?,
+                events: events
+                    .members()
+                    .map(|e| read_str(e, ""))
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+fn read_apps(json: &JsonValue) -> Result<Vec<BiliApp>, String> {
+    if json.is_null() {
+        return Ok(Vec::new());
+    }
+
+    if !json.is_array() {
+        return Err(format!("expect array but got {json}"));
+    }
+
+    json.members()
+        .map(|app| {
+            let api = read_str(&app["api"], "app")?;
+            let api = BiliApi::from_name(&api).ok_or(format!("unknown bili api {api}"))?;
+
+            Ok(BiliApp {
+                name: read_str(&app["name"], "")?,
+                base_url: read_str(&app["baseUrl"], api.default_base_url())?,
+                app_key: read_str(&app["appKey"], "")?,
+                app_sec: read_str(&app["appSec"], "")?,
+                client: read_str(&app["client"], "android")?,
+                api,
+            })
+        })
+        .collect()
+}
+
+impl Config {
+    fn from_json(json: &JsonValue) -> Result<Self, String> {
+        let d = Config::default();
+
+        let server = &json["server"];
+        let database = &json["database"];
+        let backup = &json["backup"];
+        let cache = &json["cache"];
+        let webhooks = &json["webhooks"];
+        let stream = &json["stream"];
+        let sync = &json["sync"];
+        let snapshot = &json["snapshot"];
+        let security = &json["security"];
+        let bili = &json["bili"];
+        let abuse = &json["abuse"];
+        let log = &json["log"];
+
+        let format = match &log["format"] {
+            JsonValue::Null => d.log.format,
+            v => v
+                .as_str()
+                .and_then(LogFormat::from_name)
+                .ok_or(format!("unknown log format {v}"))?,
+        };
+
+        let tls = &json["tls"];
+
+        let port = read_uint(&server["port"], d.server.port as u64)?;
+
+        // 八进制字符串 如"660"
+        let mode = read_str(&server["unixSocketMode"], &format!("{:o}", d.server.unix_socket_mode))?;
+        let mode = u32::from_str_radix(&mode, 8)
+            .ok()
+            .filter(|m| *m <= 0o777)
+            .ok_or(format!("invalid unixSocketMode {mode}"))?;
+
+        Ok(Config {
+            server: ServerConfig {
+                host: read_str(&server["host"], &d.server.host)?,
+                port: u16::try_from(port).map_err(|_| format!("invalid port {port}"))?,
+                unix_socket: read_str(&server["unixSocket"], &d.server.unix_socket)?,
+                unix_socket_mode: mode,
+                max_age_secs: read_uint(&server["maxAgeSecs"], d.server.max_age_secs)?,
+                swagger_ui: read_bool(&server["swaggerUi"], d.server.swagger_ui)?,
+                trust_proxy: read_bool(&server["trustProxy"], d.server.trust_proxy)?,
+                shutdown_timeout_secs: read_uint(&server["shutdownTimeoutSecs"], d.server.shutdown_timeout_secs)?,
+            },
+            database: DatabaseConfig {
+                url: read_str(&database["url"], &d.database.url)?,
+                journal_mode: read_str(&database["journalMode"], &d.database.journal_mode)?,
+                synchronous: read_str(&database["synchronous"], &d.database.synchronous)?,
+                busy_timeout_ms: read_uint(&database["busyTimeoutMs"], d.database.busy_timeout_ms)?,
+                min_connections: read_uint(
+                    &database["minConnections"],
+                    d.database.min_connections,
+                )?,
+                max_connections: read_uint(
+                    &database["maxConnections"],
+                    d.database.max_connections,
+                )?,
+                statement_cache_capacity: read_uint(
+                    &database["statementCacheCapacity"],
+                    d.database.statement_cache_capacity,
+                )?,
+            },
+            backup: BackupConfig {
+                dir: read_str(&backup["dir"], &d.backup.dir)?,
+                interval_secs: read_uint(&backup["intervalSecs"], d.backup.interval_secs)?,
+                retention: read_uint(&backup["retention"], d.backup.retention)?,
+            },
+            cache: CacheConfig {
+                capacity: read_uint(&cache["capacity"], d.cache.capacity)?,
+                ttl_secs: read_uint(&cache["ttlSecs"], d.cache.ttl_secs)?,
+            },
+            webhooks: WebhookConfig {
+                subscriptions: read_subscriptions(&webhooks["subscriptions"])?,
+                max_attempts: read_uint(&webhooks["maxAttempts"], d.webhooks.max_attempts)?,
+                retry_base_secs: read_uint(&webhooks["retryBaseSecs"], d.webhooks.retry_base_secs)?,
+                poll_interval_secs: read_uint(
+                    &webhooks["pollIntervalSecs"],
+                    d.webhooks.poll_interval_secs,
+                )?,
+                timeout_secs: read_uint(&webhooks["timeoutSecs"], d.webhooks.timeout_secs)?,
+            },
+            stream: StreamConfig {
+                heartbeat_secs: read_uint(&stream["heartbeatSecs"], d.stream.heartbeat_secs)?,
+                poll_interval_secs: read_uint(
+                    &stream["pollIntervalSecs"],
+                    d.stream.poll_interval_secs,
+                )?,
+            },
+            sync: SyncConfig {
+                max_changes: read_uint(&sync["maxChanges"], d.sync.max_changes)?,
+                max_cursor_age_secs: read_uint(&sync["maxCursorAgeSecs"], d.sync.max_cursor_age_secs)?,
+            },
+            snapshot: SnapshotConfig {
+                signing_key_file: read_str(&snapshot["signingKeyFile"], &d.snapshot.signing_key_file)?,
+                interval_secs: read_uint(&snapshot["intervalSecs"], d.snapshot.interval_secs)?,
+            },
+            tls: TlsConfig {
+                cert_file: read_str(&tls["certFile"], &d.tls.cert_file)?,
+                key_file: read_str(&tls["keyFile"], &d.tls.key_file)?,
+                watch_interval_secs: read_uint(&tls["watchIntervalSecs"], d.tls.watch_interval_secs)?,
+            },
+            security: SecurityConfig {
+                max_failures: read_uint(&security["maxFailures"], d.security.max_failures)?,
+                lockout_secs: read_uint(&security["lockoutSecs"], d.security.lockout_secs)?,
+                max_lockout_secs: read_uint(&security["maxLockoutSecs"], d.security.max_lockout_secs)?,
+            },
+            bili: BiliConfig {
+                apps: read_apps(&bili["apps"])?,
+                connect_timeout_secs: read_uint(&bili["connectTimeoutSecs"], d.bili.connect_timeout_secs)?,
+                timeout_secs: read_uint(&bili["timeoutSecs"], d.bili.timeout_secs)?,
+                max_retries: read_uint(&bili["maxRetries"], d.bili.max_retries)?,
+                breaker_threshold: read_uint(&bili["breakerThreshold"], d.bili.breaker_threshold)?,
+                breaker_cooldown_secs: read_uint(&bili["breakerCooldownSecs"], d.bili.breaker_cooldown_secs)?,
+            },
+            abuse: AbuseConfig {
+                enabled: read_bool(&abuse["enabled"], d.abuse.enabled)?,
+                retention_secs: read_uint(&abuse["retentionSecs"], d.abuse.retention_secs)?,
+                cleanup_interval_secs: read_uint(&abuse["cleanupIntervalSecs"], d.abuse.cleanup_interval_secs)?,
+                max_keys: read_uint(&abuse["maxKeys"], d.abuse.max_keys)?,
+                max_ips: read_uint(&abuse["maxIps"], d.abuse.max_ips)?,
+                max_uids_per_ip: read_uint(&abuse["maxUidsPerIp"], d.abuse.max_uids_per_ip)?,
+                auto_gray: read_bool(&abuse["autoGray"], d.abuse.auto_gray)?,
+            },
+            log: LogConfig {
+                level: read_str(&log["level"], &d.log.level)?,
+                format,
+                dir: read_str(&log["dir"], &d.log.dir)?,
+                rotate_size_mb: read_uint(&log["rotateSizeMb"], d.log.rotate_size_mb)?,
+                rotate_daily: read_bool(&log["rotateDaily"], d.log.rotate_daily)?,
+                keep_files: read_uint(&log["keepFiles"], d.log.keep_files)?,
+            },
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let server = &self.server;
+
+        if server.port == 0 && server.unix_socket.is_empty() {
+            return Err("either port or unixSocket is required".to_owned());
+        }
+
+        if self.tls.cert_file.is_empty() != self.tls.key_file.is_empty() {
+            return Err("tls certFile and keyFile must be set together".to_owned());
+        }
+
+        let db = &self.database;
+
+        if db.is_postgres() {
+            PgConnectOptions::from_str(&db.url)
+                .map_err(|e| format!("invalid database url {}: {e}", db.url))?;
+        } else if !db.is_sqlite() {
+            return Err(format!("unsupported database url {}", db.url));
+        }
+
+        SqliteJournalMode::from_str(&db.journal_mode)
+            .map_err(|_| format!("invalid journal mode {}", db.journal_mode))?;
+
+        SqliteSynchronous::from_str(&db.synchronous)
+            .map_err(|_| format!("invalid synchronous {}", db.synchronous))?;
+
+        if db.max_connections == 0 {
+            return Err("maxConnections must be greater than 0".to_owned());
+        }
+
+        if db.min_connections > db.max_connections {
+            return Err(format!(
+                "minConnections({}) must not be greater than maxConnections({})",
+                db.min_connections, db.max_connections
+            ));
+        }
+
+        if self.backup.retention == 0 {
+            return Err("backup retention must be greater than 0".to_owned());
+        }
+
+        let webhooks = &self.webhooks;
+
+        if webhooks.max_attempts == 0 || webhooks.poll_interval_secs == 0 {
+            return Err("webhook maxAttempts and pollIntervalSecs must be greater than 0".to_owned());
+        }
+
+        if self.stream.heartbeat_secs == 0 || self.stream.poll_interval_secs == 0 {
+            return Err("stream heartbeatSecs and pollIntervalSecs must be greater than 0".to_owned());
+        }
+
+        if self.sync.max_changes <= 0 || self.sync.max_cursor_age_secs == 0 {
+            return Err("sync maxChanges and maxCursorAgeSecs must be greater than 0".to_owned());
+        }
+
+        if self.snapshot.interval_secs == 0 {
+            return Err("snapshot intervalSecs must be greater than 0".to_owned());
+        }
+
+        if self.security.lockout_secs > self.security.max_lockout_secs {
+            return Err("security lockoutSecs must not be greater than maxLockoutSecs".to_owned());
+        }
+
+        if self.bili.timeout_secs == 0 || self.bili.connect_timeout_secs == 0 {
+            return Err("bili timeoutSecs and connectTimeoutSecs must be greater than 0".to_owned());
+        }
+
+        let abuse = &self.abuse;
+
+        if abuse.retention_secs == 0 || abuse.cleanup_interval_secs == 0 {
+            return Err("abuse retentionSecs and cleanupIntervalSecs must be greater than 0".to_owned());
+        }
+
+        if abuse.max_keys < 2 || abuse.max_ips < 2 || abuse.max_uids_per_ip < 2 {
+            return Err("abuse maxKeys, maxIps and maxUidsPerIp must be at least 2".to_owned());
+        }
+
+        flexi_logger::LogSpecification::parse(&self.log.level)
+            .map_err(|e| format!("invalid log level {}: {e}", self.log.level))?;
+
+        for (i, app) in self.bili.apps.iter().enumerate() {
+            if app.name.is_empty() || app.app_key.is_empty() || app.app_sec.is_empty() {
+                return Err(format!("bili app #{i} requires name, appKey and appSec"));
+            }
+
+            if self.bili.apps[..i].iter().any(|a| a.name == app.name) {
+                return Err(format!("duplicate bili app {}", app.name));
+            }
+
+            if !app.base_url.starts_with("http://") && !app.base_url.starts_with("https://") {
+                return Err(format!("invalid baseUrl {} of bili app {}", app.base_url, app.name));
+            }
+        }
+
+        for (i, sub) in webhooks.subscriptions.iter().enumerate() {
+            if sub.name.is_empty() || sub.url.is_empty() || sub.secret.is_empty() {
+                return Err(format!("webhook subscription #{i} requires name, url and secret"));
+            }
+
+            if webhooks.subscriptions[..i].iter().any(|s| s.name == sub.name) {
+                return Err(format!("duplicate webhook subscription {}", sub.name));
+            }
+
+            if let Some(e) = sub.events.iter().find(|e| !["black", "white", "gray", "none"].contains(&e.as_str())) {
+                return Err(format!("unknown webhook event {e} in {}", sub.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 只有服务器需要的配置 命令行工具不检查
+    pub fn validate_server(&self) -> Result<(), String> {
+        // 源码中不内置appkey 未配置时所有access_key查询都会失败
+        if self.bili.apps.is_empty() {
+            return Err("bili.apps is empty, configure at least one app with appKey and appSec".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+pub fn config_path() -> String {
+    std::env::var("BRBS_CONFIG").unwrap_or(DEFAULT_CONFIG_PATH.to_owned())
+}
+
+/// 读取并校验配置文件 文件不存在时使用默认配置
+pub fn load() -> Result<Config, String> {
+    let path = config_path();
+
+    let config = match fs::read_to_string(&path) {
+        Ok(s) => {
+            let json = json::parse(&s).map_err(|e| format!("cannot parse {path}: {e}"))?;
+            Config::from_json(&json).map_err(|e| format!("invalid config {path}: {e}"))?
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(format!("cannot read {path}: {e}")),
+    };
+
+    config.validate()?;
+
+    Ok(config)
+}
+
+pub fn init() -> Result<(), String> {
+    let config = load()?;
+    *CONFIG.write().unwrap() = Arc::new(config);
+    Ok(())
+}
+
+/// 重新读取配置文件 读取或校验失败时保留当前配置
+pub fn reload() -> Result<Arc<Config>, String> {
+    let config = load()?;
+    config.validate_server()?;
+
+    let config = Arc::new(config);
+    *CONFIG.write().unwrap() = config.clone();
+    Ok(config)
+}
+
+pub fn get() -> Arc<Config> {
+    CONFIG.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use json::object;
+
+    use super::*;
+
+    #[test]
+    fn out_of_range_is_rejected() {
+        let json = object! { database: { maxConnections: 4294967296u64 } };
+        assert!(Config::from_json(&json).is_err());
+
+        let json = object! { webhooks: { maxAttempts: 4294967295u64 } };
+        assert_eq!(Config::from_json(&json).unwrap().webhooks.max_attempts, u32::MAX);
+
+        let json = object! { sync: { maxChanges: u64::MAX } };
+        assert!(Config::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn empty_apps_only_fails_server() {
+        let config = Config::from_json(&object! {}).unwrap();
+        assert!(config.bili.apps.is_empty());
+        assert!(config.validate().is_ok());
+        assert!(config.validate_server().is_err());
+
+        let json = object! { bili: { apps: [{ name: "android", appKey: "key", appSec: "sec" }] } };
+        let config = Config::from_json(&json).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.validate_server().is_ok());
+    }
+}
@@ -0,0 +1,369 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use log::{error, info};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    cache, configs,
+    enums::{DeliveryStatus, RevokeError, Status},
+    stream,
+    structs::{
+        AdminKey, AuditEntry, AuditFilter, BiliProfile, IpSightings, Reason, UidSightings, User, UserProfile, UserSummary,
+        WebhookDelivery,
+    },
+    utils,
+};
+
+mod postgres;
+mod sqlite;
+
+/// owner的role 可以存在多个 lvl固定为127
+pub const OWNER_ROLE: &str = "owner";
+
+use self::{postgres::PgStorage, sqlite::SqliteStorage};
+
+/// 持久化接口 具体实现由数据库地址的scheme决定
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn create_tables(&self);
+
+    async fn vacuum_into(&self, path: &str) -> bool;
+
+    /// 等待借出的连接归还后关闭连接池
+    async fn close(&self);
+
+    async fn gen_key(&self, lvl: i8, role: &str) -> Option<String>;
+
+    async fn get_admin_key(&self, key: &str) -> Option<AdminKey>;
+
+    async fn get_admin_key_by_id(&self, id: i64) -> Option<AdminKey>;
+
+    async fn get_admin_key_by_hash(&self, hash: &str) -> Option<AdminKey>;
+
+    async fn count_owner_keys(&self) -> i64;
+
+    /// 只能重新生成role为owner的key
+    async fn regen_owner_key(&self, key: &str) -> Option<String>;
+
+    /// 不会吊销owner 没有该role的key时也返回true 数据库出错时返回false
+    async fn revoke_admin_key_by_role(&self, role: &str) -> bool;
+
+    /// 最后一个owner不会被吊销 吊销失败时通过RevokeError返回原因
+    /// 删除前锁定全部owner 并发吊销时不会删除最后一个owner
+    async fn revoke_admin_key(&self, id: i64) -> Result<(), RevokeError>;
+
+    async fn list_admin_keys(&self) -> Vec<AdminKey>;
+
+    async fn get_user_by_id(&self, uid: i64) -> User;
+
+    async fn get_last_reason(&self, uid: i64) -> Option<Reason>;
+
+    /// 用户的变更记录 按id降序
+    async fn get_reasons_by_uid(&self, uid: i64, limit: i64) -> Vec<Reason>;
+
+    async fn count_black_times(&self, uid: i64) -> i64;
+
+    /// 在一条SQL中查询users和reasons
+    async fn get_user_summary(&self, uid: i64) -> UserSummary;
+
+    async fn count_total_by_status(&self, status: &Status) -> i64;
+
+    /// 在一个事务中更新状态、写入reasons记录和待投递的webhook 返回新增的reasons记录 失败时返回None
+    async fn do_op(&self, uid: i64, op: &Status, op_role: &str, reason: &str) -> Option<Reason>;
+
+    /// 最新一条reasons记录的id 没有记录时为0
+    async fn get_last_reason_id(&self) -> i64;
+
+    /// id大于给定值的reasons记录 按id升序 id按提交顺序递增 不会出现较小的id晚于较大的id可见
+    async fn get_reasons_after(&self, id: i64, limit: i64) -> Vec<Reason>;
+
+    /// id在(since, until]之间有变更的用户及其当前状态 最多返回limit条
+    async fn get_changed_users(&self, since: i64, until: i64, limit: i64) -> Vec<(i64, Status)>;
+
+    /// 状态不为None的全部用户 按uid升序
+    async fn get_marked_users(&self) -> Vec<(i64, Status)>;
+
+    /// 取出一条待投递且到达重试时间的记录 同时将重试时间推迟到lease_until 其他实例不会取出同一条
+    async fn claim_due_webhook(&self, now: i64, lease_until: i64) -> Option<WebhookDelivery>;
+
+    async fn update_webhook(&self, delivery: &WebhookDelivery) -> bool;
+
+    async fn list_webhooks(&self, status: Option<&DeliveryStatus>, limit: i64) -> Vec<WebhookDelivery>;
+
+    /// 首次出现时写入first_seen 之后只更新name、level和last_seen
+    async fn upsert_profile(&self, profile: &BiliProfile, now: i64) -> bool;
+
+    async fn get_profile(&self, uid: i64) -> Option<UserProfile>;
+
+    async fn record_sighting(&self, key_hash: &str, uid: i64, ip: &str, now: i64) -> bool;
+
+    /// since之后该uid使用过的access_key和来源IP数量
+    async fn count_sightings(&self, uid: i64, since: i64) -> UidSightings;
+
+    /// access_key或来源IP数量达到阈值的uid 按access_key数量降序
+    async fn list_uid_sightings(&self, since: i64, min_keys: i64, min_ips: i64, limit: i64) -> Vec<UidSightings>;
+
+    /// 查询过的uid数量达到阈值的IP 按uid数量降序
+    async fn list_ip_sightings(&self, since: i64, min_uids: i64, limit: i64) -> Vec<IpSightings>;
+
+    /// 删除before之前的记录 返回删除的数量
+    async fn purge_sightings(&self, before: i64) -> u64;
+
+    async fn insert_audit(&self, entry: &AuditEntry) -> bool;
+
+    /// 符合条件的审计日志 按id降序
+    async fn list_audit(&self, filter: &AuditFilter, limit: i64) -> Vec<AuditEntry>;
+}
+
+lazy_static::lazy_static! {
+    static ref STORAGE: Box<dyn Storage> = {
+        let config = &configs::get().database;
+
+        if config.is_sqlite() {
+            Box::new(SqliteStorage::new(config))
+        } else {
+            Box::new(PgStorage::new(config))
+        }
+    };
+}
+
+/// keys表中按key_hash查找key 避免直接以key作为查询条件
+fn hash_admin_key(key: &str) -> String {
+    utils::to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+fn gen_rand_key() -> String {
+    let mut rng = rand::thread_rng();
+
+    let mut key = String::new();
+
+    for _ in 0..32 {
+        let ch = rng.gen_range('a'..='z');
+        let upper = rng.gen_bool(0.5);
+
+        key.push(if upper { ch.to_ascii_uppercase() } else { ch });
+    }
+
+    key
+}
+
+pub fn database_file() -> PathBuf {
+    let url = &configs::get().database.url;
+    let path = url
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+
+    PathBuf::from(path.split('?').next().unwrap_or_default())
+}
+
+pub async fn close() {
+    STORAGE.close().await;
+    info!("Database closed");
+}
+
+pub async fn vacuum_into(path: &str) -> bool {
+    if !configs::get().database.is_sqlite() {
+        error!("Backup is only supported by sqlite, use pg_dump instead");
+        return false;
+    }
+
+    STORAGE.vacuum_into(path).await
+}
+
+pub async fn gen_key(lvl: i8, role: &str) -> Option<String> {
+    STORAGE.gen_key(lvl, role).await
+}
+
+/// owner的lvl固定为127 其他role为0-127
+pub fn valid_key_lvl(role: &str, lvl: i8) -> bool {
+    match role == OWNER_ROLE {
+        true => lvl == 127,
+        false => lvl >= 0,
+    }
+}
+
+pub async fn count_owner_keys() -> i64 {
+    STORAGE.count_owner_keys().await
+}
+
+/// 按key原文查找 不是常量时间比较 认证请使用match_admin_key
+pub async fn get_admin_key(key: &str) -> Option<AdminKey> {
+    STORAGE.get_admin_key(key).await
+}
+
+pub async fn get_admin_key_by_id(id: i64) -> Option<AdminKey> {
+    STORAGE.get_admin_key_by_id(id).await
+}
+
+/// 认证使用 按SHA-256在索引中查找 再进行常量时间比较 避免通过响应时间猜测key
+pub async fn match_admin_key(key: &str) -> Option<AdminKey> {
+    let k = STORAGE.get_admin_key_by_hash(&hash_admin_key(key)).await?;
+
+    match bool::from(k.key.as_bytes().ct_eq(key.as_bytes())) {
+        true => Some(k),
+        false => None,
+    }
+}
+
+async fn gen_owner_key() -> Option<String> {
+    match STORAGE.count_owner_keys().await {
+        0 => gen_key(127, OWNER_ROLE).await,
+        n => {
+            info!("{n} owner key(s) already exist");
+            None
+        }
+    }
+}
+
+pub async fn regen_owner_key(key: &str) -> Option<String> {
+    STORAGE.regen_owner_key(key).await
+}
+
+pub async fn revoke_admin_key_by_role(role: &str) -> bool {
+    STORAGE.revoke_admin_key_by_role(role).await
+}
+
+pub async fn revoke_admin_key(id: i64) -> Result<(), RevokeError> {
+    STORAGE.revoke_admin_key(id).await
+}
+
+pub async fn list_admin_keys() -> Vec<AdminKey> {
+    STORAGE.list_admin_keys().await
+}
+
+/// 创建或升级数据表 不生成owner
+pub async fn migrate() {
+    STORAGE.create_tables().await;
+}
+
+/// 建表 没有owner时生成一个并返回
+pub async fn prepare() -> Option<String> {
+    info!("Start prepare database");
+
+    migrate().await;
+
+    let owner = gen_owner_key().await;
+
+    info!("Finish prepare database");
+
+    owner
+}
+
+pub async fn get_user_by_id(uid: i64) -> User {
+    if let Some(user) = cache::get(uid) {
+        return user;
+    }
+
+    let user = STORAGE.get_user_by_id(uid).await;
+    cache::fill(user.clone());
+    user
+}
+
+pub async fn get_last_reason(uid: i64) -> Option<Reason> {
+    STORAGE.get_last_reason(uid).await
+}
+
+pub async fn get_reasons_by_uid(uid: i64, limit: i64) -> Vec<Reason> {
+    STORAGE.get_reasons_by_uid(uid, limit).await
+}
+
+pub async fn count_black_times(uid: i64) -> i64 {
+    STORAGE.count_black_times(uid).await
+}
+
+pub async fn get_user_summary(uid: i64) -> UserSummary {
+    STORAGE.get_user_summary(uid).await
+}
+
+pub async fn count_total_by_status(status: &Status) -> i64 {
+    STORAGE.count_total_by_status(status).await
+}
+
+pub async fn do_op(uid: i64, op: &Status, op_role: &str, reason: &str) -> bool {
+    match STORAGE.do_op(uid, op, op_role, reason).await {
+        Some(r) => {
+            cache::put(User {
+                uid,
+                status: op.clone(),
+                last_reason: Some(reason.to_owned()),
+                last_change: r.id,
+            });
+            stream::publish(r.id);
+            true
+        }
+        // 用户状态可能已经更新 丢弃缓存以免读到旧值
+        None => {
+            cache::remove(uid);
+            false
+        }
+    }
+}
+
+pub async fn get_last_reason_id() -> i64 {
+    STORAGE.get_last_reason_id().await
+}
+
+pub async fn get_reasons_after(id: i64, limit: i64) -> Vec<Reason> {
+    STORAGE.get_reasons_after(id, limit).await
+}
+
+pub async fn get_changed_users(since: i64, until: i64, limit: i64) -> Vec<(i64, Status)> {
+    STORAGE.get_changed_users(since, until, limit).await
+}
+
+pub async fn get_marked_users() -> Vec<(i64, Status)> {
+    STORAGE.get_marked_users().await
+}
+
+pub async fn claim_due_webhook(now: i64, lease_until: i64) -> Option<WebhookDelivery> {
+    STORAGE.claim_due_webhook(now, lease_until).await
+}
+
+pub async fn update_webhook(delivery: &WebhookDelivery) -> bool {
+    STORAGE.update_webhook(delivery).await
+}
+
+pub async fn list_webhooks(status: Option<&DeliveryStatus>, limit: i64) -> Vec<WebhookDelivery> {
+    STORAGE.list_webhooks(status, limit).await
+}
+
+pub async fn record_profile(profile: BiliProfile) -> bool {
+    STORAGE.upsert_profile(&profile, utils::current_milliseconds()).await
+}
+
+pub async fn get_profile(uid: i64) -> Option<UserProfile> {
+    STORAGE.get_profile(uid).await
+}
+
+pub async fn record_sighting(key_hash: &str, uid: i64, ip: &str) -> bool {
+    STORAGE
+        .record_sighting(key_hash, uid, ip, utils::current_milliseconds())
+        .await
+}
+
+pub async fn count_sightings(uid: i64, since: i64) -> UidSightings {
+    STORAGE.count_sightings(uid, since).await
+}
+
+pub async fn list_uid_sightings(since: i64, min_keys: i64, min_ips: i64, limit: i64) -> Vec<UidSightings> {
+    STORAGE.list_uid_sightings(since, min_keys, min_ips, limit).await
+}
+
+pub async fn list_ip_sightings(since: i64, min_uids: i64, limit: i64) -> Vec<IpSightings> {
+    STORAGE.list_ip_sightings(since, min_uids, limit).await
+}
+
+pub async fn purge_sightings(before: i64) -> u64 {
+    STORAGE.purge_sightings(before).await
+}
+
+pub async fn insert_audit(entry: &AuditEntry) -> bool {
+    STORAGE.insert_audit(entry).await
+}
+
+pub async fn list_audit(filter: &AuditFilter, limit: i64) -> Vec<AuditEntry> {
+    STORAGE.list_audit(filter, limit).await
+}
@@ -0,0 +1,1033 @@
+use std::{str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use log::{error, info};
+use sqlx::{
+    sqlite::{
+        SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow, SqliteSynchronous,
+    },
+    Row, Sqlite, SqlitePool, Transaction,
+};
+
+use crate::{
+    configs::DatabaseConfig,
+    enums::{AuditResult, DeliveryStatus, RevokeError, Status},
+    structs::{
+        AdminKey, AuditEntry, AuditFilter, BiliProfile, IpSightings, Reason, UidSightings, User, UserProfile, UserSummary,
+        WebhookDelivery,
+    },
+    utils, webhook,
+};
+
+use super::{gen_rand_key, hash_admin_key, Storage};
+
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(config: &DatabaseConfig) -> Self {
+        // 配置已在启动时校验
+        let options = SqliteConnectOptions::from_str(&config.url)
+            .unwrap()
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::from_str(&config.journal_mode).unwrap())
+            .synchronous(SqliteSynchronous::from_str(&config.synchronous).unwrap())
+            .busy_timeout(Duration::from_millis(config.busy_timeout_ms))
+            .statement_cache_capacity(config.statement_cache_capacity);
+
+        let pool = SqlitePoolOptions::new()
+            .min_connections(config.min_connections)
+            .max_connections(config.max_connections)
+            .connect_lazy_with(options);
+
+        SqliteStorage { pool }
+    }
+}
+
+fn reason_from_row(r: &SqliteRow) -> Reason {
+    Reason {
+        id: r.get(0),
+        uid: r.get(1),
+        op: Status::from(r.get(2)),
+        op_role: r.try_get(3).unwrap_or("无".to_owned()),
+        reason: r.try_get(4).unwrap_or("无".to_owned()),
+        op_time: r.get(5),
+    }
+}
+
+fn admin_key_from_row(r: &SqliteRow) -> AdminKey {
+    AdminKey {
+        id: r.get(0),
+        key: r.get(1),
+        lvl: r.get(2),
+        role: r.get(3),
+    }
+}
+
+fn audit_from_row(r: &SqliteRow) -> AuditEntry {
+    AuditEntry {
+        id: r.get(0),
+        key_id: r.try_get(1).unwrap_or(None),
+        role: r.try_get(2).unwrap_or(None),
+        action: r.get(3),
+        target: r.get(4),
+        ip: r.get(5),
+        result: AuditResult::from(r.get(6)),
+        created_at: r.get(7),
+    }
+}
+
+fn profile_from_row(r: &SqliteRow) -> UserProfile {
+    UserProfile {
+        uid: r.get(0),
+        name: r.get(1),
+        level: r.get(2),
+        first_seen: r.get(3),
+        last_seen: r.get(4),
+    }
+}
+
+fn uid_sightings_from_row(r: &SqliteRow) -> UidSightings {
+    UidSightings {
+        uid: r.get(0),
+        keys: r.get(1),
+        ips: r.get(2),
+        last_seen: r.get(3),
+    }
+}
+
+fn webhook_from_row(r: &SqliteRow) -> WebhookDelivery {
+    WebhookDelivery {
+        id: r.get(0),
+        subscription: r.get(1),
+        url: r.get(2),
+        payload: r.get(3),
+        status: DeliveryStatus::from(r.get(5)),
+        attempts: r.get(4),
+        next_attempt: r.get(6),
+        last_error: r.try_get(7).unwrap_or(None),
+        created_at: r.get(8),
+        updated_at: r.get(9),
+    }
+}
+
+// do_op的各个步骤 在调用者的事务中执行
+async fn apply_op(
+    db: &mut Transaction<'_, Sqlite>,
+    uid: i64,
+    op: &Status,
+    op_role: &str,
+    reason: &str,
+) -> Result<Reason, sqlx::Error> {
+    // 先写入取得写锁 之后读到的状态在提交前不会被其他连接修改
+    let sql = r#"INSERT INTO reasons (uid, op, op_role, reason, op_time) VALUES ($1, $2, $3, $4, $5)"#;
+
+    let op_time = utils::current_milliseconds();
+
+    let ret = sqlx::query(sql)
+        .bind(uid)
+        .bind(op.into())
+        .bind(op_role)
+        .bind(reason)
+        .bind(op_time)
+        .execute(&mut *db)
+        .await?;
+
+    let change = Reason {
+        id: ret.last_insert_rowid(),
+        uid,
+        op: op.clone(),
+        op_role: op_role.to_owned(),
+        reason: reason.to_owned(),
+        op_time,
+    };
+
+    let sql = r#"SELECT status FROM users WHERE uid = $1"#;
+
+    let from = sqlx::query(sql)
+        .bind(uid)
+        .fetch_optional(&mut *db)
+        .await?
+        .map(|r| Status::from(r.get(0)))
+        .unwrap_or(Status::None);
+
+    let sql = r#"INSERT OR REPLACE INTO users (uid, status, last_reason) VALUES ($1, $2, $3)"#;
+
+    sqlx::query(sql)
+        .bind(uid)
+        .bind(op.into())
+        .bind(reason)
+        .execute(&mut *db)
+        .await?;
+
+    let sql = r#"INSERT INTO webhook_outbox (subscription, url, payload, status, attempts, next_attempt, created_at, updated_at)
+    VALUES ($1, $2, $3, 0, 0, $4, $4, $4)"#;
+
+    for hook in webhook::pending(&change, &from) {
+        sqlx::query(sql)
+            .bind(&hook.subscription)
+            .bind(&hook.url)
+            .bind(&hook.payload)
+            .bind(op_time)
+            .execute(&mut *db)
+            .await?;
+    }
+
+    Ok(change)
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    async fn vacuum_into(&self, path: &str) -> bool {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"VACUUM INTO $1"#;
+
+        let ret = sqlx::query(sql).bind(path).execute(&mut db).await;
+
+        match ret {
+            Ok(_) => {
+                info!("Successfully backed up database into {path}");
+                true
+            }
+            Err(e) => {
+                error!("Cannot back up database into {path} with error: {e}");
+                false
+            }
+        }
+    }
+
+    async fn gen_key(&self, lvl: i8, role: &str) -> Option<String> {
+        let key = gen_rand_key();
+
+        let mut db = self.pool.begin().await.unwrap();
+
+        let sql = r#"INSERT INTO keys (admin_key, key_hash, lvl, role) VALUES ($1, $2, $3, $4)"#;
+
+        let ret = sqlx::query(sql)
+            .bind(&key)
+            .bind(hash_admin_key(&key))
+            .bind(lvl)
+            .bind(role)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(_) => {
+                info!("Successfully generated {role}(lvl:{lvl}) admin key");
+                db.commit().await.unwrap();
+                Some(key)
+            }
+            Err(e) => {
+                error!("Cannot generate {role}(lvl:{lvl}) admin key with error: {e}");
+                db.rollback().await.unwrap();
+                None
+            }
+        }
+    }
+
+    async fn get_admin_key(&self, key: &str) -> Option<AdminKey> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT id, admin_key, lvl, role FROM keys WHERE admin_key = $1"#;
+
+        let ret = sqlx::query(sql).bind(key).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => Some(admin_key_from_row(&r)),
+            _ => None,
+        }
+    }
+
+    async fn get_admin_key_by_id(&self, id: i64) -> Option<AdminKey> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT id, admin_key, lvl, role FROM keys WHERE id = $1"#;
+
+        let ret = sqlx::query(sql).bind(id).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => Some(admin_key_from_row(&r)),
+            _ => None,
+        }
+    }
+
+    async fn get_admin_key_by_hash(&self, hash: &str) -> Option<AdminKey> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT id, admin_key, lvl, role FROM keys WHERE key_hash = $1"#;
+
+        let ret = sqlx::query(sql).bind(hash).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => Some(admin_key_from_row(&r)),
+            Ok(None) => None,
+            Err(e) => {
+                error!("Cannot get admin key by hash with error: {e}");
+                None
+            }
+        }
+    }
+
+    async fn count_owner_keys(&self) -> i64 {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT COUNT(*) FROM keys WHERE role = 'owner'"#;
+
+        let ret = sqlx::query(sql).fetch_one(&mut db).await;
+
+        match ret {
+            Ok(row) => row.get(0),
+            Err(e) => {
+                error!("Cannot count owner keys with error: {e}");
+                0
+            }
+        }
+    }
+
+    async fn regen_owner_key(&self, key: &str) -> Option<String> {
+        let regen = gen_rand_key();
+
+        let mut db = self.pool.begin().await.unwrap();
+
+        let sql = r#"UPDATE keys SET admin_key = $1, key_hash = $2 WHERE admin_key = $3 AND role = 'owner'"#;
+
+        let ret = sqlx::query(sql)
+            .bind(&regen)
+            .bind(hash_admin_key(&regen))
+            .bind(key)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(r) if r.rows_affected() > 0 => {
+                db.commit().await.unwrap();
+                info!("Owner key {} regenerated", utils::redact(key));
+                Some(regen)
+            }
+            _ => {
+                db.rollback().await.unwrap();
+                None
+            }
+        }
+    }
+
+    async fn revoke_admin_key_by_role(&self, role: &str) -> bool {
+        let mut db = self.pool.begin().await.unwrap();
+
+        let sql = r#"DELETE FROM keys WHERE role = $1 AND role <> 'owner'"#;
+
+        let ret = sqlx::query(sql).bind(role).execute(&mut db).await;
+
+        match ret {
+            Ok(r) => {
+                info!("Successfully revoked {} admin key(s) of {role}", r.rows_affected());
+                db.commit().await.unwrap();
+                true
+            }
+            Err(e) => {
+                error!("Cannot revoke admin key of {role} with error: {e}");
+                db.rollback().await.unwrap();
+                false
+            }
+        }
+    }
+
+    async fn revoke_admin_key(&self, id: i64) -> Result<(), RevokeError> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        // 写操作串行执行 判断和删除在同一条语句中完成
+        let sql = r#"DELETE FROM keys WHERE id = $1
+            AND NOT (role = 'owner' AND (SELECT COUNT(*) FROM keys WHERE role = 'owner') <= 1)"#;
+
+        let ret = sqlx::query(sql).bind(id).execute(&mut db).await;
+
+        match ret {
+            Ok(r) if r.rows_affected() > 0 => {
+                info!("Successfully revoked admin key#{id}");
+                return Ok(());
+            }
+            Ok(_) => (),
+            Err(e) => {
+                error!("Cannot revoke admin key#{id} with error: {e}");
+                return Err(RevokeError::Failed);
+            }
+        }
+
+        // 没有删除时区分key不存在和最后一个owner
+        let sql = r#"SELECT role FROM keys WHERE id = $1"#;
+
+        let ret = sqlx::query(sql).bind(id).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(_)) => Err(RevokeError::LastOwner),
+            Ok(None) => Err(RevokeError::NotFound),
+            Err(e) => {
+                error!("Cannot get admin key#{id} with error: {e}");
+                Err(RevokeError::Failed)
+            }
+        }
+    }
+
+    async fn list_admin_keys(&self) -> Vec<AdminKey> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT id, admin_key, lvl, role FROM keys ORDER BY id"#;
+
+        let ret = sqlx::query(sql).fetch_all(&mut db).await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(admin_key_from_row).collect(),
+            Err(e) => {
+                error!("Cannot list admin keys with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn create_tables(&self) {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS users
+        (
+            uid         BIGINT PRIMARY KEY,
+            status      SMALLINT NOT NULL DEFAULT 0,
+            last_reason TEXT
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS reasons
+        (
+            id      INTEGER PRIMARY KEY AUTOINCREMENT,
+            uid     BIGINT   NOT NULL,
+            op      SMALLINT NOT NULL DEFAULT 0,
+            op_role TEXT     NOT NULL DEFAULT 'admin',
+            reason  TEXT,
+            op_time BIGINT   NOT NULL DEFAULT 0
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS keys
+        (
+            id         INTEGER PRIMARY KEY AUTOINCREMENT,
+            admin_key  VARCHAR(32) NOT NULL,
+            key_hash   TEXT,
+            lvl        SMALLINT    NOT NULL DEFAULT 1,
+            role       Text        NOT NULL
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        // 旧版本创建的keys表没有key_hash
+        let sql = r#"SELECT COUNT(*) FROM pragma_table_info('keys') WHERE name = 'key_hash'"#;
+
+        let exists: i64 = sqlx::query(sql).fetch_one(&mut db).await.unwrap().get(0);
+
+        if exists == 0 {
+            let sql = r#"ALTER TABLE keys ADD COLUMN key_hash TEXT"#;
+
+            sqlx::query(sql).execute(&mut db).await.unwrap();
+        }
+
+        let sql = r#"SELECT id, admin_key FROM keys WHERE key_hash IS NULL"#;
+
+        let rows = sqlx::query(sql).fetch_all(&mut db).await.unwrap();
+
+        for r in rows {
+            let sql = r#"UPDATE keys SET key_hash = $1 WHERE id = $2"#;
+
+            sqlx::query(sql)
+                .bind(hash_admin_key(r.get(1)))
+                .bind(r.get::<i64, _>(0))
+                .execute(&mut db)
+                .await
+                .unwrap();
+        }
+
+        let sql = r#"CREATE UNIQUE INDEX IF NOT EXISTS keys_key_hash ON keys (key_hash)"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS webhook_outbox
+        (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            subscription TEXT     NOT NULL,
+            url          TEXT     NOT NULL,
+            payload      TEXT     NOT NULL,
+            attempts     INTEGER  NOT NULL DEFAULT 0,
+            status       SMALLINT NOT NULL DEFAULT 0,
+            next_attempt BIGINT   NOT NULL DEFAULT 0,
+            last_error   TEXT,
+            created_at   BIGINT   NOT NULL DEFAULT 0,
+            updated_at   BIGINT   NOT NULL DEFAULT 0
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE INDEX IF NOT EXISTS webhook_outbox_due ON webhook_outbox (status, next_attempt)"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS audit_log
+        (
+            id         INTEGER PRIMARY KEY AUTOINCREMENT,
+            key_id     BIGINT,
+            role       TEXT,
+            action     TEXT     NOT NULL,
+            target     TEXT     NOT NULL DEFAULT '',
+            ip         TEXT     NOT NULL DEFAULT '',
+            result     SMALLINT NOT NULL DEFAULT 0,
+            created_at BIGINT   NOT NULL DEFAULT 0
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS profiles
+        (
+            uid        BIGINT  PRIMARY KEY,
+            name       TEXT    NOT NULL DEFAULT '',
+            level      INTEGER NOT NULL DEFAULT 0,
+            first_seen BIGINT  NOT NULL DEFAULT 0,
+            last_seen  BIGINT  NOT NULL DEFAULT 0
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS key_sightings
+        (
+            key_hash  TEXT   NOT NULL,
+            uid       BIGINT NOT NULL,
+            ip        TEXT   NOT NULL,
+            last_seen BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_hash, uid, ip)
+        )"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE INDEX IF NOT EXISTS key_sightings_uid ON key_sightings (uid, last_seen)"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+
+        let sql = r#"CREATE INDEX IF NOT EXISTS key_sightings_last_seen ON key_sightings (last_seen)"#;
+
+        sqlx::query(sql).execute(&mut db).await.unwrap();
+    }
+
+    async fn get_user_by_id(&self, uid: i64) -> User {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT uid, status, last_reason,
+        (SELECT MAX(id) FROM reasons WHERE reasons.uid = users.uid)
+        FROM users WHERE uid = $1;"#;
+
+        let row = sqlx::query(sql).bind(uid).fetch_optional(&mut db).await;
+
+        match row {
+            Ok(Some(r)) => User {
+                uid,
+                status: Status::from(r.get(1)),
+                last_reason: r.try_get(2).unwrap_or(None),
+                last_change: r.try_get::<Option<i64>, _>(3).unwrap_or(None).unwrap_or(0),
+            },
+            _ => User {
+                uid,
+                status: Status::None,
+                last_reason: None,
+                last_change: 0,
+            },
+        }
+    }
+
+    async fn get_last_reason(&self, uid: i64) -> Option<Reason> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql =
+            r#"SELECT * FROM (SELECT * FROM reasons ORDER BY ID DESC) WHERE uid = $1 LIMIT 1"#;
+
+        let ret = sqlx::query(sql).bind(uid).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => Some(reason_from_row(&r)),
+            _ => None,
+        }
+    }
+
+    async fn get_reasons_by_uid(&self, uid: i64, limit: i64) -> Vec<Reason> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT * FROM reasons WHERE uid = $1 ORDER BY id DESC LIMIT $2"#;
+
+        let ret = sqlx::query(sql)
+            .bind(uid)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(reason_from_row).collect(),
+            Err(e) => {
+                error!("Cannot get reasons of {uid} with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn count_black_times(&self, uid: i64) -> i64 {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT COUNT(*) FROM reasons WHERE uid = $1 AND op = 1"#;
+
+        let ret = sqlx::query(sql).bind(uid).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => {
+                let i: i64 = r.get(0);
+                i
+            }
+            _ => 0,
+        }
+    }
+
+    async fn get_user_summary(&self, uid: i64) -> UserSummary {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        // 聚合子查询总是返回一行 users中没有记录时状态为None
+        let sql = r#"SELECT users.status, users.last_reason, r.black_times, r.last_op_time, r.last_change
+        FROM (SELECT COUNT(CASE WHEN op = 1 THEN 1 END) AS black_times,
+                     MAX(op_time) AS last_op_time,
+                     MAX(id) AS last_change
+              FROM reasons WHERE uid = $1) r
+        LEFT JOIN users ON users.uid = $1"#;
+
+        let row = sqlx::query(sql).bind(uid).fetch_one(&mut db).await;
+
+        match row {
+            Ok(r) => UserSummary {
+                uid,
+                status: r
+                    .try_get::<Option<i8>, _>(0)
+                    .unwrap_or(None)
+                    .map(Status::from)
+                    .unwrap_or(Status::None),
+                last_reason: r.try_get(1).unwrap_or(None),
+                black_times: r.get(2),
+                last_op_time: r.try_get(3).unwrap_or(None),
+                last_change: r.try_get::<Option<i64>, _>(4).unwrap_or(None).unwrap_or(0),
+            },
+            Err(e) => {
+                error!("Cannot get summary of {uid} with error: {e}");
+                UserSummary {
+                    uid,
+                    status: Status::None,
+                    last_reason: None,
+                    black_times: 0,
+                    last_op_time: None,
+                    last_change: 0,
+                }
+            }
+        }
+    }
+
+    async fn count_total_by_status(&self, status: &Status) -> i64 {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT COUNT(*) FROM users WHERE status = $1"#;
+
+        let ret = sqlx::query(sql)
+            .bind(status.into())
+            .fetch_optional(&mut db)
+            .await;
+
+        match ret {
+            Ok(Some(r)) => {
+                let i: i64 = r.get(0);
+                i
+            }
+            _ => 0,
+        }
+    }
+
+    async fn do_op(&self, uid: i64, op: &Status, op_role: &str, reason: &str) -> Option<Reason> {
+        let mut db = self.pool.begin().await.unwrap();
+
+        let ret = apply_op(&mut db, uid, op, op_role, reason).await;
+
+        let ret = match ret {
+            Ok(r) => db.commit().await.map(|_| r),
+            Err(e) => {
+                db.rollback().await.unwrap();
+                Err(e)
+            }
+        };
+
+        match ret {
+            Ok(r) => {
+                info!(
+                    "User {uid} is {} now, added records where reason={reason}",
+                    op.display()
+                );
+                Some(r)
+            }
+            Err(e) => {
+                error!(
+                    "Cannot {} user {uid} where reason={reason} with error: {e}",
+                    op.display(),
+                );
+                None
+            }
+        }
+    }
+
+    async fn get_last_reason_id(&self) -> i64 {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT MAX(id) FROM reasons"#;
+
+        let ret = sqlx::query(sql).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => r.try_get::<Option<i64>, _>(0).unwrap_or(None).unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    async fn get_reasons_after(&self, id: i64, limit: i64) -> Vec<Reason> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT * FROM reasons WHERE id > $1 ORDER BY id LIMIT $2"#;
+
+        let ret = sqlx::query(sql)
+            .bind(id)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(reason_from_row).collect(),
+            Err(e) => {
+                error!("Cannot get reasons after {id} with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn get_changed_users(&self, since: i64, until: i64, limit: i64) -> Vec<(i64, Status)> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT uid, status FROM users WHERE uid IN
+        (SELECT uid FROM reasons WHERE id > $1 AND id <= $2)
+        ORDER BY uid LIMIT $3"#;
+
+        let ret = sqlx::query(sql)
+            .bind(since)
+            .bind(until)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(|r| (r.get(0), Status::from(r.get(1)))).collect(),
+            Err(e) => {
+                error!("Cannot get users changed after {since} with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn get_marked_users(&self) -> Vec<(i64, Status)> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT uid, status FROM users WHERE status != 0 ORDER BY uid"#;
+
+        let ret = sqlx::query(sql).fetch_all(&mut db).await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(|r| (r.get(0), Status::from(r.get(1)))).collect(),
+            Err(e) => {
+                error!("Cannot get marked users with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn claim_due_webhook(&self, now: i64, lease_until: i64) -> Option<WebhookDelivery> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        // 单条语句在写锁内执行 不会被其他连接同时取出
+        let sql = r#"UPDATE webhook_outbox SET next_attempt = $2 WHERE id =
+        (SELECT id FROM webhook_outbox WHERE status = 0 AND next_attempt <= $1 ORDER BY id LIMIT 1)
+        RETURNING *"#;
+
+        let ret = sqlx::query(sql)
+            .bind(now)
+            .bind(lease_until)
+            .fetch_optional(&mut db)
+            .await;
+
+        match ret {
+            Ok(row) => row.as_ref().map(webhook_from_row),
+            Err(e) => {
+                error!("Cannot claim due webhook with error: {e}");
+                None
+            }
+        }
+    }
+
+    async fn update_webhook(&self, delivery: &WebhookDelivery) -> bool {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"UPDATE webhook_outbox SET status = $1, attempts = $2, next_attempt = $3, last_error = $4, updated_at = $5
+        WHERE id = $6"#;
+
+        let status = &delivery.status;
+
+        let ret = sqlx::query(sql)
+            .bind(status.into())
+            .bind(delivery.attempts)
+            .bind(delivery.next_attempt)
+            .bind(&delivery.last_error)
+            .bind(delivery.updated_at)
+            .bind(delivery.id)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Cannot update webhook delivery {} with error: {e}", delivery.id);
+                false
+            }
+        }
+    }
+
+    async fn list_webhooks(&self, status: Option<&DeliveryStatus>, limit: i64) -> Vec<WebhookDelivery> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT * FROM webhook_outbox WHERE ($1 IS NULL OR status = $1) ORDER BY id DESC LIMIT $2"#;
+
+        let ret = sqlx::query(sql)
+            .bind(status.map(|s| s.into()))
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(webhook_from_row).collect(),
+            Err(e) => {
+                error!("Cannot list webhooks with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn upsert_profile(&self, profile: &BiliProfile, now: i64) -> bool {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"INSERT INTO profiles (uid, name, level, first_seen, last_seen) VALUES ($1, $2, $3, $4, $4)
+        ON CONFLICT (uid) DO UPDATE SET name = excluded.name, level = excluded.level, last_seen = excluded.last_seen"#;
+
+        let ret = sqlx::query(sql)
+            .bind(profile.uid)
+            .bind(&profile.name)
+            .bind(profile.level)
+            .bind(now)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Cannot save profile of {} with error: {e}", profile.uid);
+                false
+            }
+        }
+    }
+
+    async fn get_profile(&self, uid: i64) -> Option<UserProfile> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT uid, name, level, first_seen, last_seen FROM profiles WHERE uid = $1"#;
+
+        let ret = sqlx::query(sql).bind(uid).fetch_optional(&mut db).await;
+
+        match ret {
+            Ok(Some(r)) => Some(profile_from_row(&r)),
+            _ => None,
+        }
+    }
+
+    async fn record_sighting(&self, key_hash: &str, uid: i64, ip: &str, now: i64) -> bool {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"INSERT INTO key_sightings (key_hash, uid, ip, last_seen) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (key_hash, uid, ip) DO UPDATE SET last_seen = excluded.last_seen"#;
+
+        let ret = sqlx::query(sql)
+            .bind(key_hash)
+            .bind(uid)
+            .bind(ip)
+            .bind(now)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Cannot record key sighting of {uid} with error: {e}");
+                false
+            }
+        }
+    }
+
+    async fn count_sightings(&self, uid: i64, since: i64) -> UidSightings {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT $1, COUNT(DISTINCT key_hash), COUNT(DISTINCT ip), COALESCE(MAX(last_seen), 0)
+        FROM key_sightings WHERE uid = $1 AND last_seen >= $2"#;
+
+        let ret = sqlx::query(sql).bind(uid).bind(since).fetch_one(&mut db).await;
+
+        match ret {
+            Ok(r) => uid_sightings_from_row(&r),
+            Err(e) => {
+                error!("Cannot count key sightings of {uid} with error: {e}");
+                UidSightings { uid, keys: 0, ips: 0, last_seen: 0 }
+            }
+        }
+    }
+
+    async fn list_uid_sightings(&self, since: i64, min_keys: i64, min_ips: i64, limit: i64) -> Vec<UidSightings> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT uid, COUNT(DISTINCT key_hash), COUNT(DISTINCT ip), MAX(last_seen)
+        FROM key_sightings WHERE last_seen >= $1 GROUP BY uid
+        HAVING COUNT(DISTINCT key_hash) >= $2 OR COUNT(DISTINCT ip) >= $3
+        ORDER BY 2 DESC, 3 DESC LIMIT $4"#;
+
+        let ret = sqlx::query(sql)
+            .bind(since)
+            .bind(min_keys)
+            .bind(min_ips)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(uid_sightings_from_row).collect(),
+            Err(e) => {
+                error!("Cannot list key sightings with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn list_ip_sightings(&self, since: i64, min_uids: i64, limit: i64) -> Vec<IpSightings> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT ip, COUNT(DISTINCT uid), MAX(last_seen)
+        FROM key_sightings WHERE last_seen >= $1 GROUP BY ip
+        HAVING COUNT(DISTINCT uid) >= $2
+        ORDER BY 2 DESC LIMIT $3"#;
+
+        let ret = sqlx::query(sql)
+            .bind(since)
+            .bind(min_uids)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows
+                .iter()
+                .map(|r| IpSightings {
+                    ip: r.get(0),
+                    uids: r.get(1),
+                    last_seen: r.get(2),
+                })
+                .collect(),
+            Err(e) => {
+                error!("Cannot list key sightings with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn purge_sightings(&self, before: i64) -> u64 {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"DELETE FROM key_sightings WHERE last_seen < $1"#;
+
+        let ret = sqlx::query(sql).bind(before).execute(&mut db).await;
+
+        match ret {
+            Ok(r) => r.rows_affected(),
+            Err(e) => {
+                error!("Cannot purge key sightings with error: {e}");
+                0
+            }
+        }
+    }
+
+    async fn insert_audit(&self, entry: &AuditEntry) -> bool {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let result = &entry.result;
+
+        let sql = r#"INSERT INTO audit_log (key_id, role, action, target, ip, result, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)"#;
+
+        let ret = sqlx::query(sql)
+            .bind(entry.key_id)
+            .bind(&entry.role)
+            .bind(&entry.action)
+            .bind(&entry.target)
+            .bind(&entry.ip)
+            .bind(result.into())
+            .bind(entry.created_at)
+            .execute(&mut db)
+            .await;
+
+        match ret {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Cannot write audit log {} with error: {e}", entry.action);
+                false
+            }
+        }
+    }
+
+    async fn list_audit(&self, filter: &AuditFilter, limit: i64) -> Vec<AuditEntry> {
+        let mut db = self.pool.acquire().await.unwrap();
+
+        let sql = r#"SELECT * FROM audit_log
+        WHERE ($1 IS NULL OR action = $1)
+          AND ($2 IS NULL OR ip = $2)
+          AND ($3 IS NULL OR result = $3)
+          AND ($4 IS NULL OR id < $4)
+        ORDER BY id DESC LIMIT $5"#;
+
+        let ret = sqlx::query(sql)
+            .bind(&filter.action)
+            .bind(&filter.ip)
+            .bind(filter.result.as_ref().map(|r| r.into()))
+            .bind(filter.before)
+            .bind(limit)
+            .fetch_all(&mut db)
+            .await;
+
+        match ret {
+            Ok(rows) => rows.iter().map(audit_from_row).collect(),
+            Err(e) => {
+                error!("Cannot list audit log with error: {e}");
+                Vec::new()
+            }
+        }
+    }
+}
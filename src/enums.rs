@@ -3,6 +3,8 @@ pub enum Status {
     None = 0,
     Black = 1,
     White = 2,
+    // 疑似共享或泄露access_key 由滥用检测自动标记
+    Gray = 3,
 }
 
 impl Status {
@@ -11,15 +13,35 @@ impl Status {
             0 => Status::None,
             1 => Status::Black,
             2 => Status::White,
+            3 => Status::Gray,
             _ => Status::None,
         }
     }
 
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(Status::None),
+            "black" => Some(Status::Black),
+            "white" => Some(Status::White),
+            "gray" => Some(Status::Gray),
+            _ => None,
+        }
+    }
+
     pub fn into(&self) -> i8 {
         match self {
             Status::None => 0,
             Status::Black => 1,
             Status::White => 2,
+            Status::Gray => 3,
+        }
+    }
+
+    /// 灰名单只在v2中返回 v1的客户端只认识0-2 按无处理
+    pub fn into_v1(&self) -> i8 {
+        match self {
+            Status::Gray => 0,
+            s => s.into(),
         }
     }
 
@@ -28,6 +50,167 @@ impl Status {
             Status::None => "normal",
             Status::Black => "black",
             Status::White => "white",
+            Status::Gray => "gray",
+        }
+    }
+}
+
+/// B站接口的类型 决定请求的地址
+#[derive(Debug, Clone, PartialEq)]
+pub enum BiliApi {
+    // 国内版 app.bilibili.com
+    App,
+    // 国际版 app.biliintl.com
+    Intl,
+}
+
+impl BiliApi {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "app" => Some(BiliApi::App),
+            "intl" => Some(BiliApi::Intl),
+            _ => None,
+        }
+    }
+
+    pub fn default_base_url(&self) -> &str {
+        match self {
+            BiliApi::App => "https://app.bilibili.com",
+            BiliApi::Intl => "https://app.biliintl.com",
+        }
+    }
+
+    pub fn myinfo_path(&self) -> &str {
+        match self {
+            BiliApi::App => "/x/v2/account/myinfo",
+            BiliApi::Intl => "/intl/gateway/v2/app/account/myinfo",
+        }
+    }
+}
+
+/// 日志格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    // 带颜色的文本
+    Text,
+    // 每行一个JSON对象
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// 通过access_key查询用户失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum BiliError {
+    // access_key不存在或已过期
+    InvalidKey,
+    // B站返回了其他错误码 如风控拦截
+    Rejected(i64),
+    // 网络错误、超时、5xx或熔断中
+    Unavailable,
+    // 指定的app不在配置中
+    UnknownApp,
+}
+
+/// 吊销admin key失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum RevokeError {
+    // key不存在或已被吊销
+    NotFound,
+    // 不能吊销最后一个owner
+    LastOwner,
+    // 数据库错误
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryStatus {
+    Pending = 0,
+    Delivered = 1,
+    Failed = 2,
+}
+
+impl DeliveryStatus {
+    pub fn from(value: i8) -> Self {
+        match value {
+            1 => DeliveryStatus::Delivered,
+            2 => DeliveryStatus::Failed,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn into(&self) -> i8 {
+        match self {
+            DeliveryStatus::Pending => 0,
+            DeliveryStatus::Delivered => 1,
+            DeliveryStatus::Failed => 2,
+        }
+    }
+
+    pub fn display(&self) -> &str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditResult {
+    Ok = 0,
+    Denied = 1,
+    Failed = 2,
+}
+
+impl AuditResult {
+    pub fn from(value: i8) -> Self {
+        match value {
+            1 => AuditResult::Denied,
+            2 => AuditResult::Failed,
+            _ => AuditResult::Ok,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ok" => Some(AuditResult::Ok),
+            "denied" => Some(AuditResult::Denied),
+            "failed" => Some(AuditResult::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn into(&self) -> i8 {
+        match self {
+            AuditResult::Ok => 0,
+            AuditResult::Denied => 1,
+            AuditResult::Failed => 2,
+        }
+    }
+
+    pub fn display(&self) -> &str {
+        match self {
+            AuditResult::Ok => "ok",
+            AuditResult::Denied => "denied",
+            AuditResult::Failed => "failed",
+        }
+    }
+}
@@ -0,0 +1,19 @@
+pub mod abuse;
+pub mod audit;
+pub mod backup;
+pub mod bili_requests;
+pub mod cache;
+pub mod configs;
+pub mod db;
+pub mod enums;
+pub mod lockout;
+pub mod logging;
+pub mod openapi;
+pub mod reload;
+pub mod routing;
+pub mod snapshot;
+pub mod stream;
+pub mod structs;
+pub mod tls;
+pub mod utils;
+pub mod webhook;
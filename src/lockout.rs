@@ -0,0 +1,140 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use lru::LruCache;
+
+use crate::configs;
+
+// 最多记录的IP数 超出时淘汰最久没有失败的IP
+const CAPACITY: usize = 10000;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+lazy_static::lazy_static! {
+    static ref FAILURES: Mutex<LruCache<String, Failures>> = Mutex::new(LruCache::new(CAPACITY));
+}
+
+static LOCKOUTS: AtomicU64 = AtomicU64::new(0);
+
+/// 仍在锁定中时返回剩余秒数
+pub fn locked(ip: &str) -> Option<u64> {
+    let failures = FAILURES.lock().unwrap();
+    let until = failures.peek(ip)?.locked_until?;
+
+    let left = until.checked_duration_since(Instant::now())?;
+    Some(left.as_secs() + 1)
+}
+
+/// 达到阈值后每多失败一次锁定时间翻倍 不超过max_lockout_secs
+fn lockout_duration(lockout_secs: u64, max_lockout_secs: u64, over: u32) -> u64 {
+    lockout_secs
+        .saturating_mul(1 << over.min(20))
+        .min(max_lockout_secs)
+}
+
+/// 记录一次错误key 达到阈值时锁定该IP 返回锁定秒数
+pub fn fail(ip: &str) -> Option<u64> {
+    let config = &configs::get().security;
+
+    if config.max_failures == 0 {
+        return None;
+    }
+
+    let now = Instant::now();
+    let window = Duration::from_secs(config.max_lockout_secs);
+    let mut failures = FAILURES.lock().unwrap();
+
+    if !failures.contains(ip) {
+        failures.put(
+            ip.to_owned(),
+            Failures {
+                count: 0,
+                last: now,
+                locked_until: None,
+            },
+        );
+    }
+
+    let f = failures.get_mut(ip).unwrap();
+
+    // 长时间没有失败后重新计数
+    if now.duration_since(f.last) >= window {
+        f.count = 0;
+    }
+
+    f.count += 1;
+    f.last = now;
+
+    if f.count < config.max_failures {
+        return None;
+    }
+
+    let secs = lockout_duration(
+        config.lockout_secs,
+        config.max_lockout_secs,
+        f.count - config.max_failures,
+    );
+
+    f.locked_until = Some(now + Duration::from_secs(secs));
+    LOCKOUTS.fetch_add(1, Ordering::Relaxed);
+
+    warn!(
+        "Possible brute force: {ip} locked for {secs}s after {} failed key attempts",
+        f.count
+    );
+
+    Some(secs)
+}
+
+/// 使用正确的key后清除失败记录
+pub fn reset(ip: &str) {
+    FAILURES.lock().unwrap().pop(ip);
+}
+
+pub struct LockoutStats {
+    // 当前被锁定的IP数
+    pub locked: usize,
+    // 启动以来的锁定次数
+    pub lockouts: u64,
+}
+
+pub fn stats() -> LockoutStats {
+    let now = Instant::now();
+
+    let locked = FAILURES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, f)| f.locked_until.is_some_and(|t| t > now))
+        .count();
+
+    LockoutStats {
+        locked,
+        lockouts: LOCKOUTS.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_doubles_until_capped() {
+        assert_eq!(lockout_duration(60, 3600, 0), 60);
+        assert_eq!(lockout_duration(60, 3600, 1), 120);
+        assert_eq!(lockout_duration(60, 3600, 5), 1920);
+        assert_eq!(lockout_duration(60, 3600, 6), 3600);
+        assert_eq!(lockout_duration(60, 3600, u32::MAX), 3600);
+        assert_eq!(lockout_duration(u64::MAX, 3600, 20), 3600);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{cell::RefCell, future::Future, sync::Mutex, time::Instant};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use flexi_logger::{
+    style, Age, Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, FlexiLoggerError, Logger, LoggerHandle,
+    Naming, Record, TS_DASHES_BLANK_COLONS_DOT_BLANK,
+};
+use json::{object, JsonValue};
+use log::{info, warn};
+use rand::Rng;
+
+use crate::{configs::LogConfig, enums::LogFormat, utils};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// 客户端传入的X-Request-Id过长或包含其他字符时重新生成
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    // 处理请求的任务中有效 写入日志时附带
+    static REQUEST_ID: String;
+}
+
+lazy_static::lazy_static! {
+    // 重新加载配置时修改日志级别 停机时刷新缓冲
+    static ref HANDLE: Mutex<Option<LoggerHandle>> = Mutex::new(None);
+}
+
+thread_local! {
+    // 访问日志的结构化字段 只在写入该条日志时存在
+    static FIELDS: RefCell<Option<JsonValue>> = const { RefCell::new(None) };
+}
+
+/// 处理请求过程中记录的字段 请求结束时写入访问日志
+#[derive(Debug, Clone, Default)]
+pub struct RequestFields {
+    pub uid: Option<i64>,
+    pub role: Option<String>,
+    pub outcome: Option<&'static str>,
+}
+
+// 由构造响应的函数写入响应的extensions 请求中记录的outcome优先
+#[derive(Debug, Clone, Copy)]
+struct Outcome(&'static str);
+
+fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+fn write_text(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, colored: bool) -> std::io::Result<()> {
+    let level = record.level();
+    let time = now.format(TS_DASHES_BLANK_COLONS_DOT_BLANK);
+
+    let mut msg = match request_id() {
+        Some(id) => format!("[{id}] {}", record.args()),
+        None => record.args().to_string(),
+    };
+
+    FIELDS.with(|fields| {
+        if let Some(fields) = &*fields.borrow() {
+            for (k, v) in fields.entries().filter(|(_, v)| !v.is_null()) {
+                msg.push_str(&format!(" {k}={v}"));
+            }
+        }
+    });
+
+    match colored {
+        true => write!(
+            w,
+            "[{}] {} - {}",
+            style(level).paint(time),
+            style(level).paint(level.to_string()),
+            style(level).paint(msg)
+        ),
+        false => write!(w, "[{time}] {level} - {msg}"),
+    }
+}
+
+fn text_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+    write_text(w, now, record, true)
+}
+
+// 写入文件时不带颜色
+fn plain_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+    write_text(w, now, record, false)
+}
+
+fn json_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+    let mut line = object! {
+        ts: (now.now().unix_timestamp_nanos() / 1_000_000) as i64,
+        level: record.level().as_str(),
+        target: record.target(),
+        msg: record.args().to_string()
+    };
+
+    if let Some(id) = request_id() {
+        line["requestId"] = id.into();
+    }
+
+    FIELDS.with(|fields| {
+        if let Some(fields) = &*fields.borrow() {
+            for (k, v) in fields.entries().filter(|(_, v)| !v.is_null()) {
+                line[k] = v.clone();
+            }
+        }
+    });
+
+    write!(w, "{}", line.dump())
+}
+
+fn rotation(config: &LogConfig) -> Option<Criterion> {
+    let size = config.rotate_size_mb * 1024 * 1024;
+
+    match (config.rotate_daily, size) {
+        (true, 0) => Some(Criterion::Age(Age::Day)),
+        (true, size) => Some(Criterion::AgeOrSize(Age::Day, size)),
+        (false, 0) => None,
+        (false, size) => Some(Criterion::Size(size)),
+    }
+}
+
+// reqwest的debug日志会输出包含access_key的url 除非在RUST_LOG中显式指定
+fn spec(level: &str) -> String {
+    format!("reqwest=info,{level}")
+}
+
+/// 根据配置初始化日志 环境变量RUST_LOG优先于log.level
+pub fn init(config: &LogConfig) -> Result<(), FlexiLoggerError> {
+    let level = std::env::var("RUST_LOG").unwrap_or(config.level.clone());
+    let mut logger = Logger::try_with_str(spec(&level))?;
+
+    logger = match config.format {
+        LogFormat::Text => logger.format_for_stderr(text_format).format_for_files(plain_format),
+        LogFormat::Json => logger.format(json_format),
+    };
+
+    if !config.dir.is_empty() {
+        let spec = FileSpec::default()
+            .directory(&config.dir)
+            .basename("brbs")
+            .suppress_timestamp();
+
+        logger = logger
+            .log_to_file(spec)
+            .append()
+            .duplicate_to_stderr(Duplicate::Info);
+
+        if let Some(criterion) = rotation(config) {
+            let cleanup = match config.keep_files {
+                0 => Cleanup::Never,
+                n => Cleanup::KeepLogFiles(n),
+            };
+
+            logger = logger.rotate(criterion, Naming::Timestamps, cleanup);
+        }
+    }
+
+    *HANDLE.lock().unwrap() = Some(logger.start()?);
+
+    Ok(())
+}
+
+/// 只有日志级别可以在运行时修改 格式和文件设置需要重启
+pub fn reload(old: &LogConfig, new: &LogConfig) {
+    if new.level != old.level {
+        match std::env::var("RUST_LOG") {
+            Ok(_) => warn!("RUST_LOG is set, log level {} ignored", new.level),
+            Err(_) => {
+                if let Some(handle) = HANDLE.lock().unwrap().as_mut() {
+                    match handle.parse_new_spec(&spec(&new.level)) {
+                        Ok(_) => info!("Log level changed to {}", new.level),
+                        Err(e) => warn!("Cannot change log level to {} with error: {e}", new.level),
+                    }
+                }
+            }
+        }
+    }
+
+    let restart = LogConfig {
+        level: old.level.clone(),
+        ..new.clone()
+    };
+
+    if restart != *old {
+        warn!("Log format, dir and rotation changes take effect after restart");
+    }
+}
+
+pub fn shutdown() {
+    if let Some(handle) = HANDLE.lock().unwrap().take() {
+        handle.shutdown();
+    }
+}
+
+fn with_fields(req: &HttpRequest, f: impl FnOnce(&mut RequestFields)) {
+    if let Some(fields) = req.extensions_mut().get_mut::<RequestFields>() {
+        f(fields);
+    }
+}
+
+pub fn set_uid(req: &HttpRequest, uid: i64) {
+    with_fields(req, |fields| fields.uid = Some(uid));
+}
+
+pub fn set_role(req: &HttpRequest, role: &str) {
+    with_fields(req, |fields| fields.role = Some(role.to_owned()));
+}
+
+pub fn set_outcome(req: &HttpRequest, outcome: &'static str) {
+    with_fields(req, |fields| fields.outcome = Some(outcome));
+}
+
+/// 旧接口出错时HTTP状态码仍为200 通过outcome在访问日志中区分
+pub fn with_outcome(mut resp: HttpResponse, outcome: &'static str) -> HttpResponse {
+    resp.extensions_mut().insert(Outcome(outcome));
+    resp
+}
+
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+fn new_request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map(|id| id.to_owned())
+        .unwrap_or_else(|| utils::to_hex(&rand::thread_rng().gen::<[u8; 8]>()))
+}
+
+fn access_log<B>(res: &ServiceResponse<B>, start: Instant) {
+    let req = res.request();
+    let status = res.status();
+
+    let fields = req.extensions().get::<RequestFields>().cloned().unwrap_or_default();
+
+    let outcome = fields
+        .outcome
+        .or(res.response().extensions().get::<Outcome>().map(|o| o.0))
+        .unwrap_or(match status.as_u16() {
+            100..=399 => "ok",
+            400..=499 => "rejected",
+            _ => "error",
+        });
+
+    let fields = object! {
+        method: req.method().as_str(),
+        route: req.match_pattern(),
+        status: status.as_u16(),
+        uid: fields.uid,
+        role: fields.role,
+        outcome: outcome,
+        latencyMs: start.elapsed().as_millis() as u64
+    };
+
+    FIELDS.with(|f| *f.borrow_mut() = Some(fields));
+    info!(target: "access", "request completed");
+    FIELDS.with(|f| f.borrow_mut().take());
+}
+
+/// 为每个请求分配X-Request-Id 客户端传入时沿用 请求结束后写入一条访问日志
+pub fn trace<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let id = new_request_id(&req);
+    let start = Instant::now();
+
+    req.extensions_mut().insert(RequestFields::default());
+
+    let fut = REQUEST_ID.scope(id.clone(), srv.call(req));
+
+    async move {
+        let mut res = fut.await?;
+
+        REQUEST_ID.sync_scope(id.clone(), || access_log(&res, start));
+
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+
+        Ok(res)
+    }
+}
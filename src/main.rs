@@ -1,41 +1,111 @@
-use flexi_logger::{style, DeferredNow, Duplicate, Record, TS_DASHES_BLANK_COLONS_DOT_BLANK};
-
-mod configs;
-mod db;
-mod enums;
-mod routing;
-mod utils;
-mod structs;
-mod bili_requests;
-
-fn log_format(
-    w: &mut dyn std::io::Write,
-    now: &mut DeferredNow,
-    record: &Record,
-) -> Result<(), std::io::Error> {
-    let level = record.level();
-    write!(
-        w,
-        "[{}] {} - {}",
-        style(level).paint(now.format(TS_DASHES_BLANK_COLONS_DOT_BLANK)),
-        style(level).paint(record.level().to_string()),
-        style(level).paint(&record.args().to_string())
-    )
-}
+use std::sync::Arc;
+
+use log::{error, info};
+
+use brbs_rs::{
+    abuse, backup,
+    bili_requests::{BiliClient, HttpBiliClient},
+    configs, db, logging, reload, routing, snapshot, tls, webhook,
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // config 日志配置也在其中 出错时直接输出到stderr
+    if let Err(e) = configs::init() {
+        eprintln!("{e}");
+        return Err(std::io::Error::other(e));
+    }
+
     // logger
-    flexi_logger::Logger::try_with_env_or_str("info")
-        .unwrap()
-        .duplicate_to_stderr(Duplicate::Info)
-        .format(log_format)
-        .start()
-        .unwrap();
+    logging::init(&configs::get().log).map_err(std::io::Error::other)?;
+
+    let args: Vec<String> = std::env::args().collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+
+    match args[1..] {
+        // brbs-rs restore <backup file>
+        ["restore", file] => {
+            return match backup::restore(file).await {
+                true => Ok(()),
+                false => Err(std::io::Error::other("restore failed")),
+            };
+        }
+        // brbs-rs gen-snapshot-key <key file>
+        ["gen-snapshot-key", file] => {
+            return match snapshot::gen_signing_key(file) {
+                Ok(public_key) => {
+                    info!("Snapshot signing key written to {file}, public key: {public_key}");
+                    Ok(())
+                }
+                Err(e) => Err(std::io::Error::other(e)),
+            };
+        }
+        // brbs-rs verify-snapshot <snapshot file> <signature file> <public key>
+        ["verify-snapshot", file, sig, public_key] => {
+            let body = std::fs::read(file)?;
+            let sig = std::fs::read_to_string(sig)?;
+            return match snapshot::verify(&body, &sig, public_key) {
+                true => {
+                    info!("Snapshot {file} is valid");
+                    Ok(())
+                }
+                false => Err(std::io::Error::other("invalid snapshot signature")),
+            };
+        }
+        _ => {}
+    }
+
+    // 以下启动服务器
+    if let Err(e) = configs::get().validate_server() {
+        error!("{e}");
+        return Err(std::io::Error::other(e));
+    }
+
+    // 持有锁直到进程退出 服务器运行时restore会被拒绝
+    let _lock = match configs::get().database.is_sqlite() {
+        true => Some(backup::lock_database().map_err(|e| {
+            error!("{e}");
+            std::io::Error::other(e)
+        })?),
+        false => None,
+    };
+
+    // database 生成的owner key只输出到stdout 不写入日志
+    if let Some(key) = db::prepare().await {
+        println!("Generated owner key: {key}");
+    }
 
-    // database
-    db::prepare().await;
+    // scheduled backup
+    tokio::spawn(backup::run_scheduler());
+
+    // webhook delivery
+    let webhooks = tokio::spawn(webhook::run_worker());
+
+    // signed snapshot
+    tokio::spawn(snapshot::run_job());
+
+    // expired key sightings
+    tokio::spawn(abuse::run_cleanup());
+
+    // config reload
+    let bili: Arc<dyn BiliClient> = Arc::new(HttpBiliClient::new(&configs::get().bili));
+    tokio::spawn(reload::run_on_hangup(bili.clone()));
+
+    // certificate reload
+    if configs::get().tls.enabled() {
+        tokio::spawn(tls::run_watcher());
+    }
 
     // server
-    routing::run_server().await
+    routing::run_server(bili).await?;
+
+    // graceful shutdown
+    info!("Server stopped, delivering pending webhooks");
+    webhook::flush(webhooks).await;
+    db::close().await;
+
+    info!("Shutdown complete");
+    logging::shutdown();
+
+    Ok(())
 }
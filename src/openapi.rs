@@ -0,0 +1,832 @@
+use json::{array, object, JsonValue};
+
+lazy_static::lazy_static! {
+    // 文档内容固定 启动后只生成一次
+    static ref SPEC: String = build().dump();
+}
+
+pub fn spec() -> &'static str {
+    &SPEC
+}
+
+/* 描述接口的辅助函数 */
+
+fn schema_ref(name: &str) -> JsonValue {
+    object! { "$ref": format!("#/components/schemas/{name}") }
+}
+
+fn integer(description: &str) -> JsonValue {
+    object! { type: "integer", format: "int64", description: description }
+}
+
+fn string(description: &str) -> JsonValue {
+    object! { type: "string", description: description }
+}
+
+fn status_schema() -> JsonValue {
+    object! { type: "integer", enum: [0, 1, 2, 3], description: "0: 无, 1: 黑, 2: 白, 3: 灰 v1接口(/v2以外)中灰按0返回 /admin/abuse除外" }
+}
+
+fn object_schema(properties: JsonValue, required: &[&str]) -> JsonValue {
+    object! { type: "object", properties: properties, required: required }
+}
+
+/// 所有JSON接口统一使用{code, msg, data}包装 非法参数时code为400 内部错误时为500
+fn envelope(data: Option<JsonValue>) -> JsonValue {
+    let mut properties = object! {
+        code: { type: "integer", enum: [200, 400, 500] },
+        msg: { type: "string" }
+    };
+
+    if let Some(data) = data {
+        properties["data"] = data;
+    }
+
+    object_schema(properties, &["code", "msg"])
+}
+
+fn json_response(description: &str, data: Option<JsonValue>) -> JsonValue {
+    object! {
+        "200": {
+            description: description,
+            content: { "application/json": { schema: envelope(data) } }
+        }
+    }
+}
+
+fn text_response(content_type: &str, description: &str) -> JsonValue {
+    let mut content = JsonValue::new_object();
+    content[content_type] = object! { schema: { type: "string" } };
+
+    object! {
+        "200": { description: description, content: content },
+        "404": { description: "尚未生成快照" }
+    }
+}
+
+fn path_param(name: &str, schema: JsonValue) -> JsonValue {
+    object! { name: name, in: "path", required: true, schema: schema }
+}
+
+fn query_param(name: &str, schema: JsonValue) -> JsonValue {
+    object! { name: name, in: "query", required: false, schema: schema }
+}
+
+fn header_param(name: &str, schema: JsonValue) -> JsonValue {
+    object! { name: name, in: "header", required: false, schema: schema }
+}
+
+fn json_body(properties: JsonValue, required: &[&str]) -> JsonValue {
+    object! {
+        required: true,
+        content: { "application/json": { schema: object_schema(properties, required) } }
+    }
+}
+
+fn key_body() -> JsonValue {
+    json_body(object! { key: string("admin key") }, &["key"])
+}
+
+fn op_body() -> JsonValue {
+    json_body(
+        object! { uid: integer("用户uid"), key: string("admin key"), reason: string("理由") },
+        &["uid", "key", "reason"],
+    )
+}
+
+fn operation(
+    tag: &str,
+    summary: &str,
+    parameters: JsonValue,
+    body: Option<JsonValue>,
+    responses: JsonValue,
+) -> JsonValue {
+    let mut op = object! {
+        tags: [tag],
+        summary: summary,
+        parameters: parameters,
+        responses: responses
+    };
+
+    if let Some(body) = body {
+        op["requestBody"] = body;
+    }
+
+    op
+}
+
+fn too_many_requests() -> JsonValue {
+    object! {
+        description: "同一IP错误key次数过多被暂时锁定",
+        headers: { "Retry-After": { schema: { type: "integer" }, description: "剩余锁定秒数" } }
+    }
+}
+
+/// 按access_key查询时B站出错的响应
+fn upstream_errors(mut op: JsonValue) -> JsonValue {
+    op["responses"]["502"] = object! { description: "B站拒绝了请求 如风控拦截" };
+    op["responses"]["503"] = object! { description: "B站接口超时、出错或熔断中" };
+    op
+}
+
+fn deprecated(mut op: JsonValue) -> JsonValue {
+    op["deprecated"] = true.into();
+    op
+}
+
+/// v2接口使用Authorization: Bearer <key>认证
+fn secured(mut op: JsonValue) -> JsonValue {
+    op["security"] = array![object! { bearer: [] }];
+    op["responses"]["401"] = object! { description: "缺少key或key无效" };
+    op["responses"]["403"] = object! { description: "key的lvl不足" };
+    op["responses"]["429"] = too_many_requests();
+    op
+}
+
+/// v2接口通过HTTP状态码表示结果
+fn status_response(status: &str, description: &str, data: Option<JsonValue>) -> JsonValue {
+    let mut resp = JsonValue::new_object();
+    resp[status] = json_response(description, data).remove("200");
+    resp["400"] = object! { description: "非法参数" };
+    resp
+}
+
+fn components() -> JsonValue {
+    object! {
+        securitySchemes: {
+            bearer: { type: "http", scheme: "bearer", description: "admin key" }
+        },
+        schemas: {
+            UserStatus: object_schema(
+                object! {
+                    uid: integer("用户uid"),
+                    status: status_schema(),
+                    reason: string("status不为0时返回")
+                },
+                &["uid", "status"]
+            ),
+            FullStatus: object_schema(
+                object! {
+                    uid: integer("用户uid"),
+                    status: status_schema(),
+                    reason: string("status不为0时返回"),
+                    blackTimes: integer("被拉黑次数"),
+                    expiry: { type: "integer", nullable: true, description: "过期时间(毫秒) 暂不支持 固定为null" },
+                    lastOpTime: { type: "integer", nullable: true, description: "最近一次变更的时间(毫秒) 没有记录时为null" }
+                },
+                &["uid", "status", "blackTimes", "expiry", "lastOpTime"]
+            ),
+            BlackTimes: object_schema(object! { blackTimes: integer("被拉黑次数") }, &["blackTimes"]),
+            LastReason: object_schema(
+                object! {
+                    status: status_schema(),
+                    opRole: string("操作者role"),
+                    reason: string("理由"),
+                    timestamp: integer("操作时间(毫秒)"),
+                    profile: {
+                        allOf: [schema_ref("Profile")],
+                        nullable: true,
+                        description: "通过access_key查询时记录的用户信息 没有记录时为null"
+                    }
+                },
+                &["status", "opRole", "reason", "timestamp"]
+            ),
+            Profile: object_schema(
+                object! {
+                    name: string("用户名"),
+                    level: integer("等级"),
+                    firstSeen: integer("首次通过access_key查询的时间(毫秒)"),
+                    lastSeen: integer("最近通过access_key查询的时间(毫秒)")
+                },
+                &["name", "level", "firstSeen", "lastSeen"]
+            ),
+            AbuseReport: object_schema(
+                object! {
+                    uids: {
+                        type: "array",
+                        items: object_schema(
+                            object! {
+                                uid: integer("用户uid"),
+                                keys: integer("统计范围内使用的access_key数"),
+                                ips: integer("统计范围内使用的IP数"),
+                                lastSeen: integer("最近一次查询的时间(毫秒)"),
+                                status: status_schema()
+                            },
+                            &["uid", "keys", "ips", "lastSeen", "status"]
+                        )
+                    },
+                    ips: {
+                        type: "array",
+                        items: object_schema(
+                            object! {
+                                ip: string("来源IP"),
+                                uids: integer("统计范围内查询的uid数"),
+                                lastSeen: integer("最近一次查询的时间(毫秒)")
+                            },
+                            &["ip", "uids", "lastSeen"]
+                        )
+                    }
+                },
+                &["uids", "ips"]
+            ),
+            Statistics: object_schema(
+                object! {
+                    blackCount: integer("黑名单用户数"),
+                    whiteCount: integer("白名单用户数"),
+                    cache: object_schema(
+                        object! {
+                            hits: integer("缓存命中次数"),
+                            misses: integer("缓存未命中次数"),
+                            hitRate: { type: "number" },
+                            size: integer("缓存的用户数")
+                        },
+                        &["hits", "misses", "hitRate", "size"]
+                    ),
+                    security: object_schema(
+                        object! {
+                            lockedIps: integer("当前被锁定的IP数"),
+                            lockouts: integer("启动以来的锁定次数")
+                        },
+                        &["lockedIps", "lockouts"]
+                    )
+                },
+                &["blackCount", "whiteCount", "cache", "security"]
+            ),
+            Key: object_schema(object! { key: string("admin key") }, &["key"]),
+            AdminKey: object_schema(
+                object! {
+                    id: integer("key id"),
+                    key: string("admin key的前4个字符 其余以***代替"),
+                    lvl: { type: "integer", minimum: 0, maximum: 127 },
+                    role: string("role")
+                },
+                &["id", "key", "lvl", "role"]
+            ),
+            History: object_schema(
+                object! {
+                    id: integer("变更id"),
+                    status: status_schema(),
+                    opRole: string("操作者role"),
+                    reason: string("理由"),
+                    timestamp: integer("操作时间(毫秒)")
+                },
+                &["id", "status", "opRole", "reason", "timestamp"]
+            ),
+            Changes: object_schema(
+                object! {
+                    cursor: integer("下次同步时作为since传入"),
+                    full: { type: "boolean", description: "为true时changes为全部非normal用户" },
+                    changes: {
+                        type: "array",
+                        description: "[uid, status]",
+                        items: { type: "array", items: { type: "integer" }, minItems: 2, maxItems: 2 }
+                    }
+                },
+                &["cursor", "full", "changes"]
+            ),
+            Snapshot: object_schema(
+                object! {
+                    version: { type: "integer" },
+                    generatedAt: integer("生成时间(毫秒)"),
+                    cursor: integer("生成快照时最新的变更id"),
+                    count: integer("用户数"),
+                    users: {
+                        type: "array",
+                        description: "[uid, status]",
+                        items: { type: "array", items: { type: "integer" }, minItems: 2, maxItems: 2 }
+                    }
+                },
+                &["version", "generatedAt", "cursor", "count", "users"]
+            ),
+            AuditEntry: object_schema(
+                object! {
+                    id: integer("日志id"),
+                    keyId: { type: "integer", format: "int64", nullable: true, description: "操作者key的id 未知key或命令行操作时为null" },
+                    role: { type: "string", nullable: true, description: "操作者role 命令行操作时为cli" },
+                    action: string("操作 如user.black key.gen key.revoke owner.regen db.backup"),
+                    target: string("操作对象"),
+                    ip: string("来源IP 命令行操作时为local"),
+                    result: { type: "string", enum: ["ok", "denied", "failed"] },
+                    createdAt: integer("时间(毫秒)")
+                },
+                &["id", "keyId", "role", "action", "target", "ip", "result", "createdAt"]
+            ),
+            WebhookDelivery: object_schema(
+                object! {
+                    id: integer("投递id"),
+                    subscription: string("订阅名称"),
+                    url: string("投递地址"),
+                    status: { type: "string", enum: ["pending", "delivered", "failed"] },
+                    attempts: { type: "integer" },
+                    nextAttempt: integer("下次投递时间(毫秒)"),
+                    lastError: { type: "string", nullable: true },
+                    createdAt: integer("创建时间(毫秒)"),
+                    updatedAt: integer("更新时间(毫秒)")
+                },
+                &["id", "subscription", "url", "status", "attempts", "nextAttempt", "lastError", "createdAt", "updatedAt"]
+            )
+        }
+    }
+}
+
+fn build() -> JsonValue {
+    let mut paths = JsonValue::new_object();
+
+    let uid = || path_param("uid", object! { type: "integer", format: "int64" });
+    let access_key = || {
+        path_param(
+            "key",
+            object! { type: "string", description: "哔哩哔哩access_key" },
+        )
+    };
+    let access_key_header = || {
+        let mut param = header_param("X-Access-Key", object! { type: "string", description: "哔哩哔哩access_key" });
+        param["required"] = true.into();
+        param
+    };
+    let access_key_body = || {
+        json_body(
+            object! {
+                accessKey: string("哔哩哔哩access_key"),
+                app: string("access_key所属客户端 即配置中bili.apps的name 不填写时按顺序尝试")
+            },
+            &["accessKey"],
+        )
+    };
+    let app = || query_param("app", string("access_key所属客户端 即配置中bili.apps的name 不填写时按顺序尝试"));
+    let if_none_match = || header_param("If-None-Match", object! { type: "string" });
+    let cached = |description: &str, data: JsonValue| {
+        let mut resp = json_response(description, Some(data));
+        resp["304"] = object! { description: "用户未发生变化" };
+        resp
+    };
+
+    /* 查询 */
+
+    paths["/query/status/uid={uid}"]["get"] = operation(
+        "query",
+        "按uid查询状态",
+        array![uid(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("UserStatus")),
+    );
+    paths["/query/status"]["get"] = upstream_errors(operation(
+        "query",
+        "按请求头中的access_key查询状态 access_key无效时返回code 400",
+        array![access_key_header(), app(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("UserStatus")),
+    ));
+    paths["/query/status"]["post"] = upstream_errors(operation(
+        "query",
+        "按请求体中的access_key查询状态 access_key无效时返回code 400",
+        array![if_none_match()],
+        Some(access_key_body()),
+        cached("查询成功", schema_ref("UserStatus")),
+    ));
+    paths["/query/status/key={key}"]["get"] = deprecated(upstream_errors(operation(
+        "query",
+        "按access_key查询状态 access_key会出现在URL中 请改用请求头或请求体",
+        array![access_key(), app(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("UserStatus")),
+    )));
+    paths["/query/times/uid={uid}"]["get"] = operation(
+        "query",
+        "按uid查询被拉黑次数",
+        array![uid(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("BlackTimes")),
+    );
+    paths["/query/times"]["get"] = upstream_errors(operation(
+        "query",
+        "按请求头中的access_key查询被拉黑次数 access_key无效时返回code 400",
+        array![access_key_header(), app(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("BlackTimes")),
+    ));
+    paths["/query/times"]["post"] = upstream_errors(operation(
+        "query",
+        "按请求体中的access_key查询被拉黑次数 access_key无效时返回code 400",
+        array![if_none_match()],
+        Some(access_key_body()),
+        cached("查询成功", schema_ref("BlackTimes")),
+    ));
+    paths["/query/times/key={key}"]["get"] = deprecated(upstream_errors(operation(
+        "query",
+        "按access_key查询被拉黑次数 access_key会出现在URL中 请改用请求头或请求体",
+        array![access_key(), app(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("BlackTimes")),
+    )));
+    paths["/query/full/uid={uid}"]["get"] = operation(
+        "query",
+        "按uid查询状态和被拉黑次数",
+        array![uid(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("FullStatus")),
+    );
+    paths["/query/full"]["get"] = upstream_errors(operation(
+        "query",
+        "按请求头中的access_key查询状态和被拉黑次数 access_key无效时返回code 400",
+        array![access_key_header(), app(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("FullStatus")),
+    ));
+    paths["/query/full"]["post"] = upstream_errors(operation(
+        "query",
+        "按请求体中的access_key查询状态和被拉黑次数 access_key无效时返回code 400",
+        array![if_none_match()],
+        Some(access_key_body()),
+        cached("查询成功", schema_ref("FullStatus")),
+    ));
+
+    /* 同步 */
+
+    paths["/stream/changes"]["get"] = operation(
+        "sync",
+        "订阅状态变更(Server-Sent Events)",
+        array![
+            query_param("since", integer("从该变更id之后开始推送")),
+            header_param("Last-Event-ID", object! { type: "string" })
+        ],
+        None,
+        object! {
+            "200": {
+                description: "每个事件的data为{id, uid, op, opTime}",
+                content: { "text/event-stream": { schema: { type: "string" } } }
+            }
+        },
+    );
+    paths["/sync"]["get"] = operation(
+        "sync",
+        "增量同步",
+        array![query_param("since", integer("上次同步返回的cursor"))],
+        None,
+        json_response("查询成功", Some(schema_ref("Changes"))),
+    );
+    paths["/snapshot/latest"]["get"] = operation(
+        "sync",
+        "最新的签名快照",
+        array![if_none_match()],
+        None,
+        object! {
+            "200": {
+                description: "X-Brbs-Signature头为对响应体的Ed25519签名(hex)",
+                content: { "application/json": { schema: schema_ref("Snapshot") } }
+            },
+            "304": { description: "快照未更新" },
+            "404": { description: "尚未生成快照" }
+        },
+    );
+    paths["/snapshot/latest.sig"]["get"] = operation(
+        "sync",
+        "最新快照的签名(hex)",
+        array![],
+        None,
+        text_response("text/plain", "签名"),
+    );
+    paths["/snapshot/pubkey"]["get"] = operation(
+        "sync",
+        "快照签名公钥(hex)",
+        array![],
+        None,
+        text_response("text/plain", "公钥"),
+    );
+
+    /* 管理 */
+
+    paths["/admin/black"]["post"] = operation(
+        "admin",
+        "拉黑",
+        array![],
+        Some(op_body()),
+        json_response("执行成功", None),
+    );
+    paths["/admin/white"]["post"] = operation(
+        "admin",
+        "加白",
+        array![],
+        Some(op_body()),
+        json_response("执行成功", None),
+    );
+    paths["/admin/none"]["post"] = operation(
+        "admin",
+        "恢复为无",
+        array![],
+        Some(op_body()),
+        json_response("执行成功", None),
+    );
+    paths["/admin/last"]["post"] = operation(
+        "admin",
+        "最近一条记录",
+        array![],
+        Some(json_body(
+            object! { uid: integer("用户uid"), key: string("admin key") },
+            &["uid", "key"],
+        )),
+        json_response(
+            "查询成功 没有记录时msg为无结果且不返回data",
+            Some(schema_ref("LastReason")),
+        ),
+    );
+    paths["/admin/statistics"]["post"] = operation(
+        "admin",
+        "统计信息",
+        array![],
+        Some(key_body()),
+        json_response("查询成功", Some(schema_ref("Statistics"))),
+    );
+
+    paths["/admin/abuse"]["post"] = operation(
+        "admin",
+        "滥用检测 需要lvl为127",
+        array![],
+        Some(json_body(
+            object! {
+                key: string("lvl为127的key"),
+                minKeys: integer("uid使用的access_key数阈值 默认为abuse.maxKeys"),
+                minIps: integer("uid使用的IP数阈值 默认为abuse.maxIps"),
+                minUids: integer("IP查询的uid数阈值 默认为abuse.maxUidsPerIp"),
+                limit: { type: "integer", minimum: 1, maximum: 500, default: 50 }
+            },
+            &["key"],
+        )),
+        json_response("查询成功", Some(schema_ref("AbuseReport"))),
+    );
+
+    /* owner */
+
+    paths["/owner/keygen"]["post"] = operation(
+        "owner",
+        "生成admin key",
+        array![],
+        Some(json_body(
+            object! {
+                key: string("lvl为127的key 生成owner时需要为owner key"),
+                role: string("新key的role"),
+                lvl: { type: "integer", minimum: 0, maximum: 127, default: 1, description: "role为owner时固定为127" }
+            },
+            &["key", "role"],
+        )),
+        json_response("生成成功", Some(schema_ref("Key"))),
+    );
+    paths["/owner/keyrevoke"]["post"] = operation(
+        "owner",
+        "移除admin key",
+        array![],
+        Some(json_body(
+            object! {
+                key: string("lvl为127的key"),
+                revokeKey: string("要移除的key"),
+                role: string("移除该role的全部key 与revokeKey二选一 不能为owner")
+            },
+            &["key"],
+        )),
+        json_response("执行成功 revokeKey不存在时code为404 最后一个owner不能移除(code 409)", None),
+    );
+    paths["/owner/keyregen"]["post"] = operation(
+        "owner",
+        "重新生成owner key",
+        array![],
+        Some(key_body()),
+        json_response("重新生成成功", Some(schema_ref("Key"))),
+    );
+    paths["/owner/backup"]["post"] = operation(
+        "owner",
+        "备份数据库",
+        array![],
+        Some(key_body()),
+        json_response(
+            "备份成功",
+            Some(object_schema(
+                object! { file: string("备份文件路径") },
+                &["file"],
+            )),
+        ),
+    );
+    paths["/owner/webhooks"]["post"] = operation(
+        "owner",
+        "查询webhook投递记录",
+        array![],
+        Some(json_body(
+            object! {
+                key: string("owner key"),
+                status: { type: "string", enum: ["pending", "delivered", "failed"] },
+                limit: { type: "integer", minimum: 1, maximum: 500, default: 50 }
+            },
+            &["key"],
+        )),
+        json_response(
+            "查询成功",
+            Some(object! { type: "array", items: schema_ref("WebhookDelivery") }),
+        ),
+    );
+
+    paths["/owner/audit"]["post"] = operation(
+        "owner",
+        "查询审计日志 按时间倒序",
+        array![],
+        Some(json_body(
+            object! {
+                key: string("owner key"),
+                action: string("按操作过滤"),
+                ip: string("按来源IP过滤"),
+                result: { type: "string", enum: ["ok", "denied", "failed"] },
+                before: integer("只返回id小于该值的记录"),
+                limit: { type: "integer", minimum: 1, maximum: 500, default: 50 }
+            },
+            &["key"],
+        )),
+        json_response(
+            "查询成功",
+            Some(object! { type: "array", items: schema_ref("AuditEntry") }),
+        ),
+    );
+
+    // 需要key的旧接口在被锁定时返回429
+    for (path, item) in paths.entries_mut() {
+        if path.starts_with("/admin/") || path.starts_with("/owner/") {
+            item["post"]["responses"]["429"] = too_many_requests();
+        }
+    }
+
+    /* v2 */
+
+    paths["/v2/users/{uid}"]["get"] = operation(
+        "v2",
+        "查询状态",
+        array![uid(), if_none_match()],
+        None,
+        cached("查询成功", schema_ref("UserStatus")),
+    );
+    paths["/v2/users/{uid}/status"]["put"] = secured(operation(
+        "v2",
+        "修改状态",
+        array![uid()],
+        Some(json_body(
+            object! { status: status_schema(), reason: string("理由") },
+            &["status", "reason"],
+        )),
+        status_response("200", "执行成功", Some(schema_ref("UserStatus"))),
+    ));
+    paths["/v2/users/{uid}/profile"]["get"] = secured(operation(
+        "v2",
+        "通过access_key查询时记录的用户信息",
+        array![uid()],
+        None,
+        {
+            let mut resp = status_response("200", "查询成功", Some(schema_ref("Profile")));
+            resp["404"] = object! { description: "没有该用户的记录" };
+            resp
+        },
+    ));
+    paths["/v2/users/{uid}/history"]["get"] = secured(operation(
+        "v2",
+        "变更记录 按时间倒序",
+        array![
+            uid(),
+            query_param(
+                "limit",
+                object! { type: "integer", minimum: 1, maximum: 500, default: 20 }
+            )
+        ],
+        None,
+        status_response(
+            "200",
+            "查询成功",
+            Some(object! { type: "array", items: schema_ref("History") }),
+        ),
+    ));
+    paths["/v2/stats"]["get"] = secured(operation(
+        "v2",
+        "统计信息",
+        array![],
+        None,
+        status_response("200", "查询成功", Some(schema_ref("Statistics"))),
+    ));
+    paths["/v2/abuse"]["get"] = secured(operation(
+        "v2",
+        "滥用检测 需要lvl为127",
+        array![
+            query_param("minKeys", object! { type: "integer", minimum: 1 }),
+            query_param("minIps", object! { type: "integer", minimum: 1 }),
+            query_param("minUids", object! { type: "integer", minimum: 1 }),
+            query_param(
+                "limit",
+                object! { type: "integer", minimum: 1, maximum: 500, default: 50 }
+            )
+        ],
+        None,
+        status_response("200", "查询成功", Some(schema_ref("AbuseReport"))),
+    ));
+    paths["/v2/keys"]["get"] = secured(operation(
+        "v2",
+        "全部admin key 需要owner key",
+        array![],
+        None,
+        status_response(
+            "200",
+            "查询成功",
+            Some(object! { type: "array", items: schema_ref("AdminKey") }),
+        ),
+    ));
+    paths["/v2/keys"]["post"] = secured(operation(
+        "v2",
+        "生成admin key 需要lvl为127",
+        array![],
+        Some(json_body(
+            object! {
+                role: string("新key的role 生成owner需要owner key"),
+                lvl: { type: "integer", minimum: 0, maximum: 127, default: 1, description: "role为owner时固定为127" }
+            },
+            &["role"],
+        )),
+        status_response(
+            "201",
+            "生成成功",
+            Some(object_schema(
+                object! { id: integer("key id 用于DELETE /v2/keys/{id}"), key: string("admin key") },
+                &["id", "key"],
+            )),
+        ),
+    ));
+    paths["/v2/keys"]["delete"] = secured(operation(
+        "v2",
+        "移除某个role的全部key 需要lvl为127 role不能为owner",
+        array![object! { name: "role", in: "query", required: true, schema: { type: "string" } }],
+        None,
+        object! {
+            "204": { description: "执行成功" },
+            "400": { description: "非法参数" },
+            "500": { description: "内部错误" }
+        },
+    ));
+    paths["/v2/keys/{id}"]["delete"] = secured(operation(
+        "v2",
+        "移除admin key 需要lvl为127 移除owner需要owner key",
+        array![path_param("id", integer("GET /v2/keys返回的id"))],
+        None,
+        object! {
+            "204": { description: "执行成功" },
+            "400": { description: "非法参数" },
+            "404": { description: "key不存在" },
+            "409": { description: "不能吊销最后一个owner" },
+            "500": { description: "内部错误" }
+        },
+    ));
+
+    paths["/v2/audit"]["get"] = secured(operation(
+        "v2",
+        "查询审计日志 需要owner key",
+        array![
+            query_param("action", string("按操作过滤")),
+            query_param("ip", string("按来源IP过滤")),
+            query_param(
+                "result",
+                object! { type: "string", enum: ["ok", "denied", "failed"] }
+            ),
+            query_param("before", integer("只返回id小于该值的记录")),
+            query_param(
+                "limit",
+                object! { type: "integer", minimum: 1, maximum: 500, default: 50 }
+            )
+        ],
+        None,
+        status_response(
+            "200",
+            "查询成功",
+            Some(object! { type: "array", items: schema_ref("AuditEntry") }),
+        ),
+    ));
+
+    object! {
+        openapi: "3.0.3",
+        info: {
+            title: "brbs.rs",
+            description: "哔哩漫游黑名单服务",
+            version: env!("CARGO_PKG_VERSION")
+        },
+        paths: paths,
+        components: components()
+    }
+}
+
+/// Swagger UI页面 资源从CDN加载
+pub fn swagger_ui() -> &'static str {
+    r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>brbs.rs API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>"##
+}
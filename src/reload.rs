@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use log::{error, info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{bili_requests::BiliClient, configs, logging, tls};
+
+/// 重新读取配置文件 读取或校验失败时保留原配置
+pub fn reload(bili: &dyn BiliClient) -> bool {
+    let old = configs::get();
+
+    let new = match configs::reload() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Cannot reload config, keep the current one: {e}");
+            return false;
+        }
+    };
+
+    logging::reload(&old.log, &new.log);
+    bili.reload(&new.bili);
+
+    // 证书文件路径可以修改 启用或关闭HTTPS需要重启
+    if new.tls.enabled() != old.tls.enabled() {
+        warn!("Enabling or disabling TLS takes effect after restart");
+    } else if new.tls.enabled() {
+        tls::reload();
+    }
+
+    // 其他配置在每次使用时读取 以下只在启动时生效
+    let (s, o) = (&new.server, &old.server);
+    if s.host != o.host
+        || s.port != o.port
+        || s.unix_socket != o.unix_socket
+        || s.unix_socket_mode != o.unix_socket_mode
+        || s.shutdown_timeout_secs != o.shutdown_timeout_secs
+    {
+        warn!("Server host, port, unixSocket and shutdownTimeoutSecs changes take effect after restart");
+    }
+
+    if new.database != old.database {
+        warn!("Database changes take effect after restart");
+    }
+
+    if new.cache.capacity != old.cache.capacity {
+        warn!("Cache capacity changes take effect after restart");
+    }
+
+    info!("Config reloaded from {}", configs::config_path());
+    true
+}
+
+/// 收到SIGHUP时重新加载配置 不影响已建立的连接
+pub async fn run_on_hangup(bili: Arc<dyn BiliClient>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Cannot listen for SIGHUP with error: {e}");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received, reloading config");
+        reload(&*bili);
+    }
+}
@@ -1,20 +1,42 @@
+use std::{
+    collections::HashMap,
+    fs::{self, DirBuilder, Permissions},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::UnixListener,
+    },
+    sync::Arc,
+};
+
 use actix_web::{
     get,
-    http::header::ContentType,
-    web::{post, Bytes, Path, self},
-    App, HttpResponse, HttpServer,
+    http::{
+        header::{self, ContentType, HeaderValue},
+        StatusCode,
+    },
+    web::{post, Bytes, Data, Path, Query, ServiceConfig, self},
+    App, HttpRequest, HttpResponse, HttpServer,
 };
+use futures_util::stream;
 
-use json::object;
+use json::{object, JsonValue};
 use log::debug;
 
 use crate::{
-    bili_requests, configs, db,
-    enums::{self, Status},
-    structs::User,
-    utils::get_response_json,
+    abuse, audit, backup,
+    bili_requests::BiliClient,
+    cache, configs, db, lockout, logging, openapi, snapshot, tls,
+    stream as change_stream,
+    enums::{self, AuditResult, BiliError, DeliveryStatus, RevokeError, Status},
+    structs::{AdminKey, AuditEntry, AuditFilter, BiliProfile, User, UserProfile, UserSummary},
+    utils::{self, get_response_json, redact},
 };
 
+mod v2;
+
+// 通过请求头传递access_key 避免出现在URL中
+const ACCESS_KEY_HEADER: &str = "X-Access-Key";
+
 fn make_json_http(json: String) -> HttpResponse {
     HttpResponse::Ok()
         .insert_header(ContentType::json())
@@ -36,7 +58,25 @@ fn invalid_param() -> HttpResponse {
         msg: "非法参数"
     }
     .dump();
-    make_json_http(s)
+    logging::with_outcome(make_json_http(s), "invalid_param")
+}
+
+fn last_owner() -> HttpResponse {
+    let s = object! {
+        code: 409,
+        msg: "不能吊销最后一个owner"
+    }
+    .dump();
+    logging::with_outcome(make_json_http(s), "conflict")
+}
+
+fn key_not_found() -> HttpResponse {
+    let s = object! {
+        code: 404,
+        msg: "key不存在"
+    }
+    .dump();
+    logging::with_outcome(make_json_http(s), "not_found")
 }
 
 fn internal_error() -> HttpResponse {
@@ -45,10 +85,150 @@ fn internal_error() -> HttpResponse {
         msg: "内部错误"
     }
     .dump();
-    make_json_http(s)
+    logging::with_outcome(make_json_http(s), "error")
+}
+
+/// access_key无效时与参数错误相同 B站出错时返回502/503 便于客户端区分
+fn bili_failed(e: BiliError) -> HttpResponse {
+    let (status, code, msg, outcome) = match e {
+        BiliError::InvalidKey => (StatusCode::OK, 400, "access_key无效", "invalid_key"),
+        BiliError::Rejected(_) => (StatusCode::BAD_GATEWAY, 502, "B站拒绝了请求", "upstream_rejected"),
+        BiliError::Unavailable => (StatusCode::SERVICE_UNAVAILABLE, 503, "B站接口暂时不可用", "upstream_unavailable"),
+        BiliError::UnknownApp => (StatusCode::OK, 400, "app不存在", "unknown_app"),
+    };
+
+    let s = object! {
+        code: code,
+        msg: msg
+    }
+    .dump();
+
+    let resp = HttpResponse::build(status)
+        .insert_header(ContentType::json())
+        .body(s);
+
+    logging::with_outcome(resp, outcome)
+}
+
+/// 在后台记录用户信息和access_key的使用情况 不影响查询的响应时间
+fn seen(req: &HttpRequest, key: &str, profile: BiliProfile) -> i64 {
+    let uid = profile.uid;
+    tokio::spawn(db::record_profile(profile));
+    tokio::spawn(abuse::record(key.to_owned(), uid, client_ip(req)));
+    uid
+}
+
+/// 开启trustProxy时只使用X-Forwarded-For的最后一项 即最近一层代理看到的地址
+/// 前面的项可以由客户端任意填写
+/// 通过Unix socket连接且没有该头时为None
+fn client_addr(req: &HttpRequest) -> Option<String> {
+    if configs::get().server.trust_proxy {
+        let forwarded = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .last()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty());
+
+        if let Some(ip) = forwarded {
+            return Some(ip.to_owned());
+        }
+    }
+
+    req.peer_addr().map(|addr| addr.ip().to_string())
+}
+
+fn client_ip(req: &HttpRequest) -> String {
+    client_addr(req).unwrap_or_else(|| "unknown".to_owned())
+}
+
+enum AuthError {
+    // key不存在
+    Unknown,
+    // lvl或role不满足要求
+    Forbidden,
+    // 错误次数过多 剩余锁定秒数
+    Locked(u64),
+}
+
+/// 校验key的lvl和role 不通过时写入审计日志
+/// 错误的key按来源IP计数 超过阈值后锁定该IP 无法获得来源IP时不计数
+async fn authorize(
+    req: &HttpRequest,
+    key: &str,
+    lvl: i8,
+    role: Option<&str>,
+    action: &str,
+    target: &str,
+) -> Result<AdminKey, AuthError> {
+    let addr = client_addr(req);
+    let ip = addr.as_deref().unwrap_or("unknown");
+
+    // 锁定期间不再查询数据库和写入审计日志
+    if let Some(secs) = addr.as_deref().and_then(lockout::locked) {
+        logging::set_outcome(req, "locked");
+        return Err(AuthError::Locked(secs));
+    }
+
+    match db::match_admin_key(key).await {
+        Some(k) if k.lvl >= lvl && role.is_none_or(|r| r == k.role) => {
+            if let Some(addr) = &addr {
+                lockout::reset(addr);
+            }
+            logging::set_role(req, &k.role);
+            Ok(k)
+        }
+        Some(k) => {
+            if let Some(addr) = &addr {
+                lockout::reset(addr);
+            }
+            logging::set_role(req, &k.role);
+            logging::set_outcome(req, "forbidden");
+            audit::record(Some(&k), ip, action, target, AuditResult::Denied).await;
+            Err(AuthError::Forbidden)
+        }
+        None => {
+            audit::record(None, ip, action, target, AuditResult::Denied).await;
+
+            match addr.as_deref().and_then(lockout::fail) {
+                Some(secs) => {
+                    logging::set_outcome(req, "locked");
+                    audit::record(None, ip, "auth.lockout", &format!("{secs}s"), AuditResult::Denied).await;
+                    Err(AuthError::Locked(secs))
+                }
+                None => {
+                    logging::set_outcome(req, "unauthorized");
+                    Err(AuthError::Unknown)
+                }
+            }
+        }
+    }
+}
+
+fn too_many_requests(secs: u64) -> HttpResponse {
+    let s = object! {
+        code: 429,
+        msg: "错误次数过多 请稍后再试"
+    }
+    .dump();
+
+    HttpResponse::TooManyRequests()
+        .insert_header(ContentType::json())
+        .insert_header((header::RETRY_AFTER, secs))
+        .body(s)
 }
 
-async fn make_op(data: Bytes, op: enums::Status) -> HttpResponse {
+// 旧接口认证失败时统一返回非法参数
+fn auth_failed(e: AuthError) -> HttpResponse {
+    match e {
+        AuthError::Locked(secs) => too_many_requests(secs),
+        _ => invalid_param(),
+    }
+}
+
+async fn make_op(req: HttpRequest, data: Bytes, op: enums::Status) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -64,24 +244,94 @@ async fn make_op(data: Bytes, op: enums::Status) -> HttpResponse {
         None => return invalid_param(),
     };
 
-    let exec_role = match db::get_admin_key_role(key).await {
-        Some(role) => role,
-        None => return invalid_param(),
-    };
-
     let reason = match json["reason"].as_str() {
         Some(key) => key,
         None => return invalid_param(),
     };
 
-    debug!("Recv make black uid={id} key={key} reason={reason}");
+    let action = format!("user.{}", op.display());
+    let target = id.to_string();
+    logging::set_uid(&req, id);
+
+    let exec = match authorize(&req, key, 0, None, &action, &target).await {
+        Ok(k) => k,
+        Err(e) => return auth_failed(e),
+    };
+
+    debug!("Recv make black uid={id} key={} reason={reason}", redact(key));
+
+    let ok = db::do_op(id, &op, &exec.role, reason).await;
+    audit::record(Some(&exec), &client_ip(&req), &action, &target, audit::result_of(ok)).await;
+
+    match ok {
+        true => act_success(),
+        false => internal_error(),
+    }
+}
+
+fn cache_control() -> String {
+    match configs::get().server.max_age_secs {
+        0 => "no-cache".to_owned(),
+        secs => format!("public, max-age={secs}"),
+    }
+}
+
+// If-None-Match命中时返回304
+fn not_modified(req: &HttpRequest, etag: &str) -> Option<HttpResponse> {
+    let inm = req.headers().get(header::IF_NONE_MATCH)?.to_str().ok()?;
+
+    let matched = inm
+        .split(',')
+        .map(|t| t.trim().trim_start_matches("W/"))
+        .any(|t| t == "*" || t == etag);
+
+    if !matched {
+        return None;
+    }
+
+    Some(
+        HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, cache_control()))
+            .finish(),
+    )
+}
+
+fn make_cacheable_json_http(json: String, etag: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(ContentType::json())
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control()))
+        .body(json)
+}
+
+fn status_etag(user: &User) -> String {
+    let status = &user.status;
+    format!("\"s-{}-{}-{}\"", user.uid, user.last_change, status.into())
+}
 
-    db::do_op(id, &op, &exec_role, reason).await;
+fn times_etag(user: &User) -> String {
+    format!("\"t-{}-{}\"", user.uid, user.last_change)
+}
 
-    act_success()
+/// v1接口使用 灰名单按无返回
+fn v1_user(mut user: User) -> User {
+    if user.status == Status::Gray {
+        user.status = Status::None;
+        user.last_reason = None;
+    }
+    user
 }
 
-fn query_result(user: User) -> HttpResponse {
+fn query_result(req: &HttpRequest, user: User) -> HttpResponse {
+    logging::set_uid(req, user.uid);
+
+    let etag = status_etag(&user);
+
+    if let Some(resp) = not_modified(req, &etag) {
+        return resp;
+    }
+
     let ret = match user.status {
         Status::None => object! {
             code: 200,
@@ -112,20 +362,162 @@ fn query_result(user: User) -> HttpResponse {
             }
         }
         .dump(),
+        Status::Gray => object! {
+            code: 200,
+            msg: "查询成功",
+            data: {
+                uid: user.uid,
+                status: 3,
+                reason: user.last_reason.unwrap_or("无".to_owned())
+            }
+        }
+        .dump(),
     };
 
-    make_json_http(ret)
+    make_cacheable_json_http(ret, &etag)
+}
+
+// 拉黑次数只在新增reasons记录时变化 用缓存的用户信息生成ETag可以省去计数查询
+async fn black_times_result(req: &HttpRequest, uid: i64) -> HttpResponse {
+    logging::set_uid(req, uid);
+
+    let user = db::get_user_by_id(uid).await;
+    let etag = times_etag(&user);
+
+    if let Some(resp) = not_modified(req, &etag) {
+        return resp;
+    }
+
+    let times = db::count_black_times(uid).await;
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: { blackTimes: times }
+    }
+    .dump();
+    make_cacheable_json_http(ret, &etag)
+}
+
+async fn status_by_access_key(req: &HttpRequest, bili: &dyn BiliClient, key: &str, app: Option<&str>) -> HttpResponse {
+    debug!("Recv query by key={} app={app:?}", redact(key));
+
+    let uid = match bili.get_profile_by_access_key(key, app).await {
+        Ok(profile) => seen(req, key, profile),
+        Err(e) => return bili_failed(e),
+    };
+
+    let user = db::get_user_by_id(uid).await;
+
+    query_result(req, v1_user(user))
+}
+
+async fn black_times_by_access_key(
+    req: &HttpRequest,
+    bili: &dyn BiliClient,
+    key: &str,
+    app: Option<&str>,
+) -> HttpResponse {
+    let id = bili
+        .get_profile_by_access_key(key, app)
+        .await
+        .map(|profile| seen(req, key, profile));
+
+    match id {
+        Ok(id) => {
+            debug!("Recv query black times by key={}({id})", redact(key));
+
+            black_times_result(req, id).await
+        }
+        Err(e) => bili_failed(e),
+    }
+}
+
+fn header_access_key(req: &HttpRequest) -> Option<String> {
+    let key = req.headers().get(ACCESS_KEY_HEADER)?.to_str().ok()?.trim();
+
+    (!key.is_empty()).then(|| key.to_owned())
+}
+
+// 返回access_key和可选的app
+fn body_access_key(data: Bytes) -> Option<(String, Option<String>)> {
+    let json = get_response_json(data)?;
+
+    let key = json["accessKey"].as_str().filter(|k| !k.is_empty())?.to_owned();
+    let app = json["app"].as_str().map(|s| s.to_owned());
+
+    Some((key, app))
+}
+
+// URL相同时响应取决于请求头中的access_key 避免共享缓存返回其他用户的结果
+fn vary_access_key(mut resp: HttpResponse) -> HttpResponse {
+    resp.headers_mut()
+        .insert(header::VARY, HeaderValue::from_static(ACCESS_KEY_HEADER));
+    resp
+}
+
+fn full_etag(summary: &UserSummary) -> String {
+    let status = &summary.status;
+    format!("\"f-{}-{}-{}\"", summary.uid, summary.last_change, status.into_v1())
+}
+
+async fn full_result(req: &HttpRequest, uid: i64) -> HttpResponse {
+    logging::set_uid(req, uid);
+
+    let summary = db::get_user_summary(uid).await;
+    let etag = full_etag(&summary);
+
+    if let Some(resp) = not_modified(req, &etag) {
+        return resp;
+    }
+
+    let status = &summary.status;
+    let mut data = object! {
+        uid: summary.uid,
+        status: status.into_v1(),
+        blackTimes: summary.black_times,
+        // 暂不支持过期时间
+        expiry: null,
+        lastOpTime: summary.last_op_time
+    };
+
+    if summary.status.into_v1() != 0 {
+        data["reason"] = summary.last_reason.unwrap_or("无".to_owned()).into();
+    }
+
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: data
+    }
+    .dump();
+    make_cacheable_json_http(ret, &etag)
+}
+
+async fn full_by_access_key(req: &HttpRequest, bili: &dyn BiliClient, key: &str, app: Option<&str>) -> HttpResponse {
+    let id = bili
+        .get_profile_by_access_key(key, app)
+        .await
+        .map(|profile| seen(req, key, profile));
+
+    match id {
+        Ok(id) => {
+            debug!("Recv query full by key={}({id})", redact(key));
+
+            full_result(req, id).await
+        }
+        Err(e) => bili_failed(e),
+    }
 }
 
 /** 请求部分 **/
 
 /*
 GET /query/status/uid=123456
-Response: {"code": 200, "data": {"status": 1, "reason": "评论区发送解析链接"}}
+Response: {"code": 200, "msg": "查询成功", "data": {"uid": 123456, "status": 1, "reason": "评论区发送解析链接"}}
 Status: 0: none, 1: black, 2: white
 */
 #[get("/query/status/uid={uid}")]
-async fn query_by_id(params: Path<String>) -> HttpResponse {
+async fn query_by_id(req: HttpRequest, params: Path<String>) -> HttpResponse {
     let id = params.into_inner();
 
     let id = id.parse::<i64>();
@@ -136,33 +528,67 @@ async fn query_by_id(params: Path<String>) -> HttpResponse {
 
             let user = db::get_user_by_id(id).await;
 
-            query_result(user)
+            query_result(&req, v1_user(user))
         }
         _ => invalid_param(),
     }
 }
 
+/*
+GET /query/status/key=...?app=ios
+app为可选参数 指定access_key来自哪个客户端 不填写时按顺序尝试
+access_key会出现在访问日志中 建议改用请求头或请求体传递
+*/
 #[get("/query/status/key={key}")]
-async fn query_by_key(params: Path<String>) -> HttpResponse {
+async fn query_by_key(
+    req: HttpRequest,
+    params: Path<String>,
+    query: Query<HashMap<String, String>>,
+    bili: Data<dyn BiliClient>,
+) -> HttpResponse {
     let key = params.into_inner();
+    let app = query.get("app").map(|s| s.as_str());
 
-    debug!("Recv query by key={key}");
+    status_by_access_key(&req, &**bili, &key, app).await
+}
 
-    let uid = match bili_requests::get_uid_by_access_key(&key).await {
-        Some(uid) => uid,
-        _ => return invalid_param(),
+/*
+GET /query/status?app=ios
+X-Access-Key: ...
+*/
+#[get("/query/status")]
+async fn query_by_header(
+    req: HttpRequest,
+    query: Query<HashMap<String, String>>,
+    bili: Data<dyn BiliClient>,
+) -> HttpResponse {
+    let key = match header_access_key(&req) {
+        Some(key) => key,
+        None => return invalid_param(),
     };
+    let app = query.get("app").map(|s| s.as_str());
 
-    let user = db::get_user_by_id(uid).await;
+    vary_access_key(status_by_access_key(&req, &**bili, &key, app).await)
+}
+
+/*
+POST /query/status
+{"accessKey": "...", "app": "ios"}
+*/
+async fn query_by_body(req: HttpRequest, data: Bytes, bili: Data<dyn BiliClient>) -> HttpResponse {
+    let (key, app) = match body_access_key(data) {
+        Some(ret) => ret,
+        None => return invalid_param(),
+    };
 
-    query_result(user)
+    status_by_access_key(&req, &**bili, &key, app.as_deref()).await
 }
 
 /*
 Response: {"code": 200, "msg": "查询成功", "data": {"blackTimes": 3}}
 */
 #[get("/query/times/uid={uid}")]
-async fn query_black_times_by_id(params: Path<String>) -> HttpResponse {
+async fn query_black_times_by_id(req: HttpRequest, params: Path<String>) -> HttpResponse {
     let id = params.into_inner();
 
     let id = id.parse::<i64>();
@@ -171,14 +597,7 @@ async fn query_black_times_by_id(params: Path<String>) -> HttpResponse {
         Ok(id) => {
             debug!("Recv query black times by uid={id}");
 
-            let times = db::count_black_times(id).await;
-            let ret = object! {
-                code: 200,
-                msg: "查询成功",
-                data: { blackTimes: times }
-            }
-            .dump();
-            make_json_http(ret)
+            black_times_result(&req, id).await
         }
         _ => invalid_param(),
     }
@@ -188,57 +607,294 @@ async fn query_black_times_by_id(params: Path<String>) -> HttpResponse {
 Response: {"code": 200, "msg": "查询成功", "data": {"blackTimes": 3}}
 */
 #[get("/query/times/key={key}")]
-async fn query_black_times_by_key(params: Path<String>) -> HttpResponse {
+async fn query_black_times_by_key(
+    req: HttpRequest,
+    params: Path<String>,
+    query: Query<HashMap<String, String>>,
+    bili: Data<dyn BiliClient>,
+) -> HttpResponse {
     let key = params.into_inner();
+    let app = query.get("app").map(|s| s.as_str());
+
+    black_times_by_access_key(&req, &**bili, &key, app).await
+}
+
+/*
+GET /query/times?app=ios
+X-Access-Key: ...
+*/
+#[get("/query/times")]
+async fn query_black_times_by_header(
+    req: HttpRequest,
+    query: Query<HashMap<String, String>>,
+    bili: Data<dyn BiliClient>,
+) -> HttpResponse {
+    let key = match header_access_key(&req) {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+    let app = query.get("app").map(|s| s.as_str());
+
+    vary_access_key(black_times_by_access_key(&req, &**bili, &key, app).await)
+}
+
+/*
+POST /query/times
+{"accessKey": "...", "app": "ios"}
+*/
+async fn query_black_times_by_body(req: HttpRequest, data: Bytes, bili: Data<dyn BiliClient>) -> HttpResponse {
+    let (key, app) = match body_access_key(data) {
+        Some(ret) => ret,
+        None => return invalid_param(),
+    };
+
+    black_times_by_access_key(&req, &**bili, &key, app.as_deref()).await
+}
+
+/*
+GET /query/full/uid=123456
+Response: {"code": 200, "msg": "查询成功", "data": {"uid": 123456, "status": 1, "reason": "评论区发送解析链接", "blackTimes": 3, "expiry": null, "lastOpTime": 1653490177054}}
+*/
+#[get("/query/full/uid={uid}")]
+async fn query_full_by_id(req: HttpRequest, params: Path<String>) -> HttpResponse {
+    let id = params.into_inner();
 
-    let id = bili_requests::get_uid_by_access_key(&key).await;
+    let id = id.parse::<i64>();
 
     match id {
-        Some(id) => {
-            debug!("Recv query black times by key={key}({id})");
+        Ok(id) => {
+            debug!("Recv query full by uid={id}");
 
-            let times = db::count_black_times(id).await;
-            let ret = object! {
-                code: 200,
-                msg: "查询成功",
-                data: { blackTimes: times }
-            }
-            .dump();
-            make_json_http(ret)
+            full_result(&req, id).await
         }
         _ => invalid_param(),
     }
 }
 
+/*
+GET /query/full?app=ios
+X-Access-Key: ...
+*/
+#[get("/query/full")]
+async fn query_full_by_header(
+    req: HttpRequest,
+    query: Query<HashMap<String, String>>,
+    bili: Data<dyn BiliClient>,
+) -> HttpResponse {
+    let key = match header_access_key(&req) {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+    let app = query.get("app").map(|s| s.as_str());
+
+    vary_access_key(full_by_access_key(&req, &**bili, &key, app).await)
+}
+
+/*
+POST /query/full
+{"accessKey": "...", "app": "ios"}
+*/
+async fn query_full_by_body(req: HttpRequest, data: Bytes, bili: Data<dyn BiliClient>) -> HttpResponse {
+    let (key, app) = match body_access_key(data) {
+        Some(ret) => ret,
+        None => return invalid_param(),
+    };
+
+    full_by_access_key(&req, &**bili, &key, app.as_deref()).await
+}
+
+/*
+GET /stream/changes?since=123
+Last-Event-ID: 123
+Response: text/event-stream
+id: 124
+event: change
+data: {"id": 124, "uid": 123456, "op": 1, "opTime": 1653490177054}
+*/
+#[get("/stream/changes")]
+async fn stream_changes(req: HttpRequest, query: Query<HashMap<String, String>>) -> HttpResponse {
+    // 断线重连时浏览器会自动携带Last-Event-ID
+    let since = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| query.get("since").map(|s| s.as_str()));
+
+    let since = match since {
+        Some(s) => match s.parse::<i64>() {
+            Ok(id) => Some(id),
+            _ => return invalid_param(),
+        },
+        None => None,
+    };
+
+    debug!("Recv change stream since={since:?}");
+
+    let rx = change_stream::subscribe(since);
+    let body = stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|b| (Ok::<_, actix_web::Error>(b), rx))
+    });
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+/*
+GET /sync?since=123
+Response: {"code": 200, "msg": "查询成功", "data": {"cursor": 130, "full": false, "changes": [[123456, 1], [654321, 0]]}}
+full为true时changes为全部黑白名单用户 客户端应替换本地数据
+*/
+#[get("/sync")]
+async fn sync(query: Query<HashMap<String, String>>) -> HttpResponse {
+    let since = match query.get("since").map(|s| s.parse::<i64>()) {
+        Some(Ok(id)) => id,
+        Some(_) => return invalid_param(),
+        None => 0,
+    };
+
+    debug!("Recv sync since={since}");
+
+    let config = configs::get().sync.clone();
+
+    // 先确定游标 之后的变更留给下一次同步
+    let cursor = db::get_last_reason_id().await;
+
+    let changes = match since {
+        // 游标比当前记录还新 说明客户端游标来自其他数据库
+        s if s > 0 && s <= cursor => {
+            // 客户端错过的最早一次变更太久之前 视为游标过旧
+            let oldest = db::get_reasons_after(since, 1).await.first().map(|r| r.op_time);
+            let max_age = i64::try_from(config.max_cursor_age_secs).unwrap_or(i64::MAX).saturating_mul(1000);
+            let min_time = utils::current_milliseconds().saturating_sub(max_age);
+
+            match oldest {
+                Some(t) if t < min_time => None,
+                _ => {
+                    let changes = db::get_changed_users(since, cursor, config.max_changes + 1).await;
+                    Some(changes).filter(|c| (c.len() as i64) <= config.max_changes)
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let full = changes.is_none();
+    let changes = match changes {
+        Some(changes) => changes,
+        // 旧客户端不认识灰名单 全量中不返回 以免镜像保存状态为0的记录
+        None => db::get_marked_users()
+            .await
+            .into_iter()
+            .filter(|(_, status)| *status != Status::Gray)
+            .collect(),
+    };
+
+    let changes: Vec<JsonValue> = changes
+        .iter()
+        .map(|(uid, status)| json::array![*uid, status.into_v1()])
+        .collect();
+
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: {
+            cursor: cursor,
+            full: full,
+            changes: changes
+        }
+    }
+    .dump();
+
+    make_json_http(ret)
+}
+
+/*
+GET /snapshot/latest
+Response: {"version": 1, "generatedAt": 1653490177054, "cursor": 130, "count": 2, "users": [[123456, 1], [654321, 2]]}
+X-Brbs-Signature: 对响应体的Ed25519签名(hex)
+*/
+#[get("/snapshot/latest")]
+async fn snapshot_latest(req: HttpRequest) -> HttpResponse {
+    let s = match snapshot::latest() {
+        Some(s) => s,
+        None => return not_found().await,
+    };
+
+    let etag = format!("\"snap-{}\"", s.cursor);
+
+    if let Some(resp) = not_modified(&req, &etag) {
+        return resp;
+    }
+
+    HttpResponse::Ok()
+        .insert_header(ContentType::json())
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control()))
+        .insert_header(("X-Brbs-Signature", s.signature.as_str()))
+        .body(s.body.clone())
+}
+
+/*
+GET /snapshot/latest.sig
+Response: 对应快照的Ed25519签名(hex)
+*/
+#[get("/snapshot/latest.sig")]
+async fn snapshot_signature() -> HttpResponse {
+    match snapshot::latest() {
+        Some(s) => HttpResponse::Ok()
+            .insert_header(ContentType::plaintext())
+            .body(s.signature.clone()),
+        None => not_found().await,
+    }
+}
+
+/*
+GET /snapshot/pubkey
+Response: 签名公钥(hex) 应通过其他可信渠道核对
+*/
+#[get("/snapshot/pubkey")]
+async fn snapshot_public_key() -> HttpResponse {
+    match snapshot::latest() {
+        Some(s) => HttpResponse::Ok()
+            .insert_header(ContentType::plaintext())
+            .body(s.public_key.clone()),
+        None => not_found().await,
+    }
+}
+
 /*
 Request: {"uid": 123456, "key": "...", "reason": "..."}
-Response: {"code": 200, "msg": "操作成功"}
+Response: {"code": 200, "msg": "执行成功"}
 */
-async fn make_black(data: Bytes) -> HttpResponse {
-    make_op(data, enums::Status::Black).await
+async fn make_black(req: HttpRequest, data: Bytes) -> HttpResponse {
+    make_op(req, data, enums::Status::Black).await
 }
 
 /*
 Request: {"uid": 123456, "key": "...", "reason": "..."}
-Response: {"code": 200, "msg": "操作成功"}
+Response: {"code": 200, "msg": "执行成功"}
 */
-async fn make_white(data: Bytes) -> HttpResponse {
-    make_op(data, enums::Status::White).await
+async fn make_white(req: HttpRequest, data: Bytes) -> HttpResponse {
+    make_op(req, data, enums::Status::White).await
 }
 
 /*
 Request: {"uid": 123456, "key": "...", "reason": "..."}
-Response: {"code": 200, "msg": "操作成功"}
+Response: {"code": 200, "msg": "执行成功"}
 */
-async fn make_none(data: Bytes) -> HttpResponse {
-    make_op(data, enums::Status::None).await
+async fn make_none(req: HttpRequest, data: Bytes) -> HttpResponse {
+    make_op(req, data, enums::Status::None).await
 }
 
 /*
 Request: {"lvl": [0-127], "key": "...", "role": "..."}
 Response: {"code": 200, "msg": "生成成功", "data": {"key":"..."}}
 */
-async fn key_gen(data: Bytes) -> HttpResponse {
+async fn key_gen(req: HttpRequest, data: Bytes) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -254,15 +910,31 @@ async fn key_gen(data: Bytes) -> HttpResponse {
         None => return invalid_param(),
     };
 
-    let lvl = json["lvl"].as_i8().unwrap_or(1);
+    // owner的lvl固定为127 只能由owner生成
+    let owner = role == db::OWNER_ROLE;
 
-    if !db::check_admin_key_with_lvl(key, 127).await {
-        return invalid_param();
-    }
+    let lvl = match &json["lvl"] {
+        JsonValue::Null if owner => 127,
+        JsonValue::Null => 1,
+        v => match v.as_i8() {
+            Some(lvl) if db::valid_key_lvl(role, lvl) => lvl,
+            _ => return invalid_param(),
+        },
+    };
+    let target = format!("role={role} lvl={lvl}");
+    let required = owner.then_some(db::OWNER_ROLE);
 
-    debug!("Recv key gen key={key}, role={role} lvl={lvl}");
+    let exec = match authorize(&req, key, 127, required, "key.gen", &target).await {
+        Ok(k) => k,
+        Err(e) => return auth_failed(e),
+    };
+
+    debug!("Recv key gen key={}, role={role} lvl={lvl}", redact(key));
 
-    match db::gen_key(lvl, role).await {
+    let ret = db::gen_key(lvl, role).await;
+    audit::record(Some(&exec), &client_ip(&req), "key.gen", &target, audit::result_of(ret.is_some())).await;
+
+    match ret {
         Some(k) => {
             let ret = object! {
                 code: 200,
@@ -278,9 +950,9 @@ async fn key_gen(data: Bytes) -> HttpResponse {
 
 /*
 Request: {"key": "...", "role": "..."} or {"key": "...", "revokeKey": "..."}
-Response: {"code": 200, "msg": "操作成功"}
+Response: {"code": 200, "msg": "执行成功"}
 */
-async fn key_revoke(data: Bytes) -> HttpResponse {
+async fn key_revoke(req: HttpRequest, data: Bytes) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -288,21 +960,57 @@ async fn key_revoke(data: Bytes) -> HttpResponse {
 
     if let Some(key) = json["key"].as_str() {
         if let Some(rev) = json["revokeKey"].as_str() {
-            if !db::check_admin_key_with_lvl(key, 127).await {
-                return invalid_param();
-            }
-            db::revoke_admin_key_by_key(rev).await;
-            debug!("Recv revoke key: {key}, revoked key: {rev}");
-            return act_success();
+            // 审计日志中不记录key本身
+            let revoked = db::get_admin_key(rev).await;
+            let target = match &revoked {
+                Some(k) => format!("key#{}", k.id),
+                None => "key#?".to_owned(),
+            };
+            // 只有owner能吊销owner
+            let required = match &revoked {
+                Some(k) if k.role == db::OWNER_ROLE => Some(db::OWNER_ROLE),
+                _ => None,
+            };
+            let exec = match authorize(&req, key, 127, required, "key.revoke", &target).await {
+                Ok(k) => k,
+                Err(e) => return auth_failed(e),
+            };
+            debug!("Recv revoke key: {}, revoked {target}", redact(key));
+            let ret = match &revoked {
+                Some(k) => db::revoke_admin_key(k.id).await,
+                None => Err(RevokeError::NotFound),
+            };
+            let result = match &ret {
+                Ok(()) => AuditResult::Ok,
+                Err(RevokeError::Failed) => AuditResult::Failed,
+                Err(_) => AuditResult::Denied,
+            };
+            audit::record(Some(&exec), &client_ip(&req), "key.revoke", &target, result).await;
+            return match ret {
+                Ok(()) => act_success(),
+                Err(RevokeError::NotFound) => key_not_found(),
+                Err(RevokeError::LastOwner) => last_owner(),
+                Err(RevokeError::Failed) => internal_error(),
+            };
         }
 
         if let Some(role) = json["role"].as_str() {
-            if !db::check_admin_key_with_lvl(key, 127).await {
+            let target = format!("role={role}");
+            let exec = match authorize(&req, key, 127, None, "key.revoke", &target).await {
+                Ok(k) => k,
+                Err(e) => return auth_failed(e),
+            };
+            // owner只能逐个吊销 以免误删全部owner
+            if role == db::OWNER_ROLE {
                 return invalid_param();
             }
-            db::revoke_admin_key_by_role(role).await;
-            debug!("Recv revoke key: {key}, role: {role}");
-            return act_success();
+            debug!("Recv revoke key: {}, role: {role}", redact(key));
+            let ok = db::revoke_admin_key_by_role(role).await;
+            audit::record(Some(&exec), &client_ip(&req), "key.revoke", &target, audit::result_of(ok)).await;
+            return match ok {
+                true => act_success(),
+                false => internal_error(),
+            };
         }
     }
     invalid_param()
@@ -312,7 +1020,7 @@ async fn key_revoke(data: Bytes) -> HttpResponse {
 Request: {"key": "..."}
 Response: {"code": 200, "msg": "重新生成成功", "data": {"key":"..."}}
 */
-async fn owner_key_regen(data: Bytes) -> HttpResponse {
+async fn owner_key_regen(req: HttpRequest, data: Bytes) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -320,13 +1028,16 @@ async fn owner_key_regen(data: Bytes) -> HttpResponse {
 
     match json["key"].as_str() {
         Some(s) => {
-            if !db::check_admin_key_with_role_and_lvl(s, "owner", 127).await {
-                return invalid_param();
-            }
+            let exec = match authorize(&req, s, 127, Some("owner"), "owner.regen", "").await {
+                Ok(k) => k,
+                Err(e) => return auth_failed(e),
+            };
             let k = db::regen_owner_key(s).await;
+            let target = format!("key#{}", exec.id);
+            audit::record(Some(&exec), &client_ip(&req), "owner.regen", &target, audit::result_of(k.is_some())).await;
             match k {
                 Some(k) => {
-                    debug!("Recv regen owner key: {s}, new key: {k}");
+                    debug!("Recv regen owner key: {}, new key: {}", redact(s), redact(&k));
                     let ret = object! {
                         code: 200,
                         msg: "重新生成成功",
@@ -344,9 +1055,10 @@ async fn owner_key_regen(data: Bytes) -> HttpResponse {
 
 /*
 Request: {"uid": 123456, "key": "..."}
-Response: {"code": 200, "msg":"查询成功", "data": {"status": 1, "reason": "评论区发送解析链接", "opRole": "admin", "timestamp": 1653490177054}}
+Response: {"code": 200, "msg":"查询成功", "data": {"status": 1, "reason": "评论区发送解析链接", "opRole": "admin", "timestamp": 1653490177054, "profile": {"name": "...", "level": 6, "firstSeen": 1653490177054, "lastSeen": 1653490177054}}}
+profile为通过access_key查询时记录的用户信息 没有记录时为null
 */
-async fn last_reason(data: Bytes) -> HttpResponse {
+async fn last_reason(req: HttpRequest, data: Bytes) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -362,11 +1074,13 @@ async fn last_reason(data: Bytes) -> HttpResponse {
         None => return invalid_param(),
     };
 
-    if !db::check_admin_key(key).await {
-        return invalid_param();
+    logging::set_uid(&req, id);
+
+    if let Err(e) = authorize(&req, key, 0, None, "user.last", &id.to_string()).await {
+        return auth_failed(e);
     }
 
-    debug!("Recv get last reason by key: {key}, uid: {id}");
+    debug!("Recv get last reason by key: {}, uid: {id}", redact(key));
 
     let r = match db::get_last_reason(id).await {
         Some(reason) => reason,
@@ -381,10 +1095,11 @@ async fn last_reason(data: Bytes) -> HttpResponse {
     };
 
     let op = &r.op;
-    let op = op.into();
+    let op = op.into_v1();
     let op_role = r.op_role;
     let reason = r.reason;
     let ts = r.op_time;
+    let profile = profile_json(db::get_profile(id).await);
 
     let ret = object! {
         code: 200,
@@ -393,7 +1108,8 @@ async fn last_reason(data: Bytes) -> HttpResponse {
             status: op,
             opRole: op_role,
             reason: reason,
-            timestamp: ts
+            timestamp: ts,
+            profile: profile
         }
     }
     .dump();
@@ -403,9 +1119,9 @@ async fn last_reason(data: Bytes) -> HttpResponse {
 
 /*
 Request: {"key": "..."}
-Response: {"code": 200, "msg": "查询成功", "data": {"blackCount": 1000, "whiteCount": 10}}
+Response: {"code": 200, "msg": "查询成功", "data": {"blackCount": 1000, "whiteCount": 10, "cache": {"hits": 900, "misses": 100, "hitRate": 0.9, "size": 100}, "security": {"lockedIps": 0, "lockouts": 2}}}
 */
-pub async fn statistics(data: Bytes) -> HttpResponse {
+pub async fn statistics(req: HttpRequest, data: Bytes) -> HttpResponse {
     let json = match get_response_json(data) {
         Some(json) => json,
         _ => return invalid_param(),
@@ -416,50 +1132,411 @@ pub async fn statistics(data: Bytes) -> HttpResponse {
         None => return invalid_param(),
     };
 
-    if !db::check_admin_key(key).await {
-        return invalid_param();
+    if let Err(e) = authorize(&req, key, 0, None, "stats", "").await {
+        return auth_failed(e);
     }
 
     let black = db::count_total_by_status(&Status::Black).await;
     let white = db::count_total_by_status(&Status::White).await;
+    let cache = cache::stats();
+    let lockout = lockout::stats();
 
-    debug!("Recv get statistics key: {key}");
+    debug!("Recv get statistics key: {}", redact(key));
 
     let ret = object! {
         code: 200,
         msg: "查询成功",
         data: {
             blackCount: black,
-            whiteCount: white
+            whiteCount: white,
+            cache: {
+                hits: cache.hits,
+                misses: cache.misses,
+                hitRate: cache.hit_rate(),
+                size: cache.size
+            },
+            security: {
+                lockedIps: lockout.locked,
+                lockouts: lockout.lockouts
+            }
+        }
+    }
+    .dump();
+
+    make_json_http(ret)
+}
+
+/*
+Request: {"key": "..."}
+Response: {"code": 200, "msg": "备份成功", "data": {"file": "backups/black-1653490177054.db"}}
+*/
+async fn owner_backup(req: HttpRequest, data: Bytes) -> HttpResponse {
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        _ => return invalid_param(),
+    };
+
+    let key = match json["key"].as_str() {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+
+    let exec = match authorize(&req, key, 127, Some("owner"), "db.backup", "").await {
+        Ok(k) => k,
+        Err(e) => return auth_failed(e),
+    };
+
+    debug!("Recv backup key: {}", redact(key));
+
+    let ret = backup::backup().await;
+    let target = ret.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+    audit::record(Some(&exec), &client_ip(&req), "db.backup", &target, audit::result_of(ret.is_some())).await;
+
+    match ret {
+        Some(path) => {
+            let ret = object! {
+                code: 200,
+                msg: "备份成功",
+                data: { file: path.display().to_string() }
+            }
+            .dump();
+            make_json_http(ret)
         }
+        _ => internal_error(),
+    }
+}
+
+/*
+Request: {"key": "...", "status": "pending|delivered|failed", "limit": 50}
+Response: {"code": 200, "msg": "查询成功", "data": [{"id": 1, "subscription": "discord", "url": "...", "status": "failed", "attempts": 8, "nextAttempt": 1653490177054, "lastError": "...", "createdAt": 1653490177054, "updatedAt": 1653490177054}]}
+*/
+async fn owner_webhooks(req: HttpRequest, data: Bytes) -> HttpResponse {
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        _ => return invalid_param(),
+    };
+
+    let key = match json["key"].as_str() {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+
+    let status = match json["status"].as_str() {
+        Some(s) => match DeliveryStatus::from_name(s) {
+            Some(status) => Some(status),
+            None => return invalid_param(),
+        },
+        None => None,
+    };
+
+    let limit = json["limit"].as_i64().unwrap_or(50).clamp(1, 500);
+
+    if let Err(e) = authorize(&req, key, 127, Some("owner"), "webhook.list", "").await {
+        return auth_failed(e);
+    }
+
+    debug!("Recv list webhooks key: {}", redact(key));
+
+    let deliveries: Vec<JsonValue> = db::list_webhooks(status.as_ref(), limit)
+        .await
+        .into_iter()
+        .map(|d| {
+            object! {
+                id: d.id,
+                subscription: d.subscription,
+                url: d.url,
+                status: d.status.display(),
+                attempts: d.attempts,
+                nextAttempt: d.next_attempt,
+                lastError: d.last_error,
+                createdAt: d.created_at,
+                updatedAt: d.updated_at
+            }
+        })
+        .collect();
+
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: deliveries
+    }
+    .dump();
+
+    make_json_http(ret)
+}
+
+fn profile_json(profile: Option<UserProfile>) -> JsonValue {
+    match profile {
+        Some(p) => object! {
+            name: p.name,
+            level: p.level,
+            firstSeen: p.first_seen,
+            lastSeen: p.last_seen
+        },
+        None => JsonValue::Null,
+    }
+}
+
+fn audit_json(entries: Vec<AuditEntry>) -> Vec<JsonValue> {
+    entries
+        .into_iter()
+        .map(|e| {
+            object! {
+                id: e.id,
+                keyId: e.key_id,
+                role: e.role,
+                action: e.action,
+                target: e.target,
+                ip: e.ip,
+                result: e.result.display(),
+                createdAt: e.created_at
+            }
+        })
+        .collect()
+}
+
+/*
+Request: {"key": "...", "action": "key.gen", "ip": "1.2.3.4", "result": "ok|denied|failed", "before": 100, "limit": 50}
+Response: {"code": 200, "msg": "查询成功", "data": [{"id": 1, "keyId": 1, "role": "owner", "action": "key.gen", "target": "role=helper lvl=1", "ip": "1.2.3.4", "result": "ok", "createdAt": 1653490177054}]}
+*/
+async fn owner_audit(req: HttpRequest, data: Bytes) -> HttpResponse {
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        _ => return invalid_param(),
+    };
+
+    let key = match json["key"].as_str() {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+
+    let result = match json["result"].as_str() {
+        Some(s) => match AuditResult::from_name(s) {
+            Some(result) => Some(result),
+            None => return invalid_param(),
+        },
+        None => None,
+    };
+
+    let filter = AuditFilter {
+        action: json["action"].as_str().map(|s| s.to_owned()),
+        ip: json["ip"].as_str().map(|s| s.to_owned()),
+        result,
+        before: json["before"].as_i64(),
+    };
+
+    let limit = json["limit"].as_i64().unwrap_or(50).clamp(1, 500);
+
+    let exec = match authorize(&req, key, 127, Some("owner"), "audit.list", "").await {
+        Ok(k) => k,
+        Err(e) => return auth_failed(e),
+    };
+
+    debug!("Recv list audit log key: {}", redact(key));
+
+    let entries = audit_json(db::list_audit(&filter, limit).await);
+    audit::record(Some(&exec), &client_ip(&req), "audit.list", "", AuditResult::Ok).await;
+
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: entries
+    }
+    .dump();
+
+    make_json_http(ret)
+}
+
+/// 阈值为None时使用配置中的值
+async fn abuse_json(min_keys: Option<i64>, min_ips: Option<i64>, min_uids: Option<i64>, limit: i64) -> JsonValue {
+    let (uids, ips) = abuse::report(min_keys, min_ips, min_uids, limit).await;
+
+    let mut suspects = Vec::new();
+
+    for s in uids {
+        let user = db::get_user_by_id(s.uid).await;
+        let status = &user.status;
+        suspects.push(object! {
+            uid: s.uid,
+            keys: s.keys,
+            ips: s.ips,
+            lastSeen: s.last_seen,
+            status: status.into()
+        });
+    }
+
+    let ips: Vec<JsonValue> = ips
+        .into_iter()
+        .map(|s| object! { ip: s.ip, uids: s.uids, lastSeen: s.last_seen })
+        .collect();
+
+    object! {
+        uids: suspects,
+        ips: ips
+    }
+}
+
+/*
+Request: {"key": "...", "minKeys": 5, "minIps": 20, "minUids": 10, "limit": 50}
+Response: {"code": 200, "msg": "查询成功", "data": {"uids": [{"uid": 123456, "keys": 6, "ips": 3, "lastSeen": 1653490177054, "status": 3}], "ips": [{"ip": "1.2.3.4", "uids": 12, "lastSeen": 1653490177054}]}}
+*/
+async fn abuse_report(req: HttpRequest, data: Bytes) -> HttpResponse {
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        _ => return invalid_param(),
+    };
+
+    let key = match json["key"].as_str() {
+        Some(key) => key,
+        None => return invalid_param(),
+    };
+
+    let limit = json["limit"].as_i64().unwrap_or(50).clamp(1, 500);
+
+    if let Err(e) = authorize(&req, key, 127, None, "abuse.report", "").await {
+        return auth_failed(e);
+    }
+
+    debug!("Recv abuse report key: {}", redact(key));
+
+    let data = abuse_json(
+        json["minKeys"].as_i64(),
+        json["minIps"].as_i64(),
+        json["minUids"].as_i64(),
+        limit,
+    )
+    .await;
+
+    let ret = object! {
+        code: 200,
+        msg: "查询成功",
+        data: data
     }
     .dump();
 
     make_json_http(ret)
 }
 
+/*
+GET /openapi.json
+Response: OpenAPI 3文档
+*/
+#[get("/openapi.json")]
+async fn openapi_spec() -> HttpResponse {
+    make_json_http(openapi::spec().to_owned())
+}
+
+/*
+GET /docs
+Response: Swagger UI页面 需要开启server.swaggerUi
+*/
+#[get("/docs")]
+async fn swagger_ui() -> HttpResponse {
+    if !configs::get().server.swagger_ui {
+        return not_found().await;
+    }
+
+    HttpResponse::Ok()
+        .insert_header(ContentType::html())
+        .body(openapi::swagger_ui())
+}
+
 async fn not_found() -> HttpResponse {
     HttpResponse::NotFound().body("")
 }
 
-pub async fn run_server() -> std::io::Result<()> {
-    HttpServer::new(|| {
+/// 注册全部接口 需要另外通过app data提供BiliClient
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(query_by_id)
+        .service(query_by_key)
+        .service(query_by_header)
+        .service(query_black_times_by_id)
+        .service(query_black_times_by_key)
+        .service(query_black_times_by_header)
+        .service(query_full_by_id)
+        .service(query_full_by_header)
+        .service(stream_changes)
+        .service(sync)
+        .service(snapshot_latest)
+        .service(snapshot_signature)
+        .service(snapshot_public_key)
+        .service(openapi_spec)
+        .service(swagger_ui)
+        .service(v2::scope())
+        .route("/query/status", post().to(query_by_body))
+        .route("/query/times", post().to(query_black_times_by_body))
+        .route("/query/full", post().to(query_full_by_body))
+        .route("/admin/black", post().to(make_black))
+        .route("/admin/white", post().to(make_white))
+        .route("/admin/none", post().to(make_none))
+        .route("/admin/last", post().to(last_reason))
+        .route("/admin/statistics", post().to(statistics))
+        .route("/admin/abuse", post().to(abuse_report))
+        .route("/owner/keygen", post().to(key_gen))
+        .route("/owner/keyrevoke", post().to(key_revoke))
+        .route("/owner/keyregen", post().to(owner_key_regen))
+        .route("/owner/backup", post().to(owner_backup))
+        .route("/owner/webhooks", post().to(owner_webhooks))
+        .route("/owner/audit", post().to(owner_audit));
+}
+
+/// 在只有当前用户能访问的临时目录中绑定并设置权限后再移动到配置的路径
+/// socket在任何时刻都不会以umask决定的默认权限出现在配置的路径上
+fn bind_private_uds(path: &str, mode: u32) -> std::io::Result<UnixListener> {
+    // 与直接绑定一样 不覆盖已存在的其他文件
+    if fs::symlink_metadata(path).is_ok() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{path} already exists")));
+    }
+
+    let dir = format!("{path}.bind-{}", std::process::id());
+    DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let tmp = format!("{dir}/socket");
+    let ret = UnixListener::bind(&tmp).and_then(|listener| {
+        fs::set_permissions(&tmp, Permissions::from_mode(mode))?;
+        fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+
+    let _ = fs::remove_dir_all(&dir);
+    ret
+}
+
+/// 收到SIGTERM后停止接收新连接 等待处理中的请求完成后返回
+pub async fn run_server(bili: Arc<dyn BiliClient>) -> std::io::Result<()> {
+    let config = configs::get();
+    let tls = tls::server_config().map_err(std::io::Error::other)?;
+
+    let mut server = HttpServer::new(move || {
         App::new()
-            .service(query_by_id)
-            .service(query_by_key)
-            .service(query_black_times_by_id)
-            .service(query_black_times_by_key)
-            .route("/admin/black", post().to(make_black))
-            .route("/admin/white", post().to(make_white))
-            .route("/admin/none", post().to(make_none))
-            .route("/admin/last", post().to(last_reason))
-            .route("/admin/statistics", post().to(statistics))
-            .route("/owner/keygen", post().to(key_gen))
-            .route("/owner/keyrevoke", post().to(key_revoke))
-            .route("/owner/keyregen", post().to(owner_key_regen))
+            .app_data(Data::from(bili.clone()))
+            .wrap_fn(logging::trace)
+            .configure(configure)
             .default_service(web::route().to(not_found))
-    })
-    .bind(("127.0.0.1", configs::SERVER_PORT))?
-    .run()
-    .await
+    });
+
+    let addr = (config.server.host.as_str(), config.server.port);
+
+    if config.server.port > 0 {
+        server = match tls {
+            Some(tls) => server.bind_rustls(addr, tls)?,
+            None => server.bind(addr)?,
+        };
+    }
+
+    if !config.server.unix_socket.is_empty() {
+        let path = &config.server.unix_socket;
+
+        // 上次退出时留下的socket文件会导致绑定失败
+        if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+
+        server = server.listen_uds(bind_private_uds(path, config.server.unix_socket_mode)?)?;
+    }
+
+    server
+        .shutdown_timeout(config.server.shutdown_timeout_secs)
+        .run()
+        .await
 }
@@ -0,0 +1,566 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    delete, get,
+    http::{header, StatusCode},
+    post, put,
+    web::{self, Bytes, Path, Query},
+    HttpRequest, HttpResponse, Scope,
+};
+use json::{object, JsonValue};
+use log::debug;
+
+use crate::{
+    audit, cache, db,
+    enums::{AuditResult, RevokeError, Status},
+    lockout, logging,
+    structs::{AdminKey, AuditFilter},
+    utils::{get_response_json, redact},
+};
+
+use super::{abuse_json, audit_json, client_ip, profile_json, query_result, too_many_requests, AuthError};
+
+// history默认和最大返回的记录数
+const DEFAULT_HISTORY_LIMIT: i64 = 20;
+const MAX_HISTORY_LIMIT: i64 = 500;
+
+// v2接口使用HTTP状态码表示结果 响应体仍为{code, msg, data}
+
+fn make_json(status: StatusCode, json: JsonValue) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/json")
+        .body(json.dump())
+}
+
+fn ok(data: JsonValue) -> HttpResponse {
+    make_json(
+        StatusCode::OK,
+        object! { code: 200, msg: "查询成功", data: data },
+    )
+}
+
+fn error(status: StatusCode, msg: &str) -> HttpResponse {
+    make_json(status, object! { code: status.as_u16(), msg: msg })
+}
+
+fn bad_request() -> HttpResponse {
+    error(StatusCode::BAD_REQUEST, "非法参数")
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .content_type("application/json")
+        .body(object! { code: 401, msg: "未授权" }.dump())
+}
+
+fn forbidden() -> HttpResponse {
+    error(StatusCode::FORBIDDEN, "权限不足")
+}
+
+/// Authorization: Bearer <key>
+fn bearer_key(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|k| k.trim())
+}
+
+/// 校验key存在且lvl和role满足要求 key不存在时返回401 权限不足时返回403 被锁定时返回429
+async fn authorize(
+    req: &HttpRequest,
+    lvl: i8,
+    role: Option<&str>,
+    action: &str,
+    target: &str,
+) -> Result<AdminKey, HttpResponse> {
+    let key = match bearer_key(req) {
+        Some(key) => key,
+        None => {
+            audit::record(None, &client_ip(req), action, target, AuditResult::Denied).await;
+            return Err(unauthorized());
+        }
+    };
+
+    match super::authorize(req, key, lvl, role, action, target).await {
+        Ok(k) => Ok(k),
+        Err(AuthError::Unknown) => Err(unauthorized()),
+        Err(AuthError::Forbidden) => Err(forbidden()),
+        Err(AuthError::Locked(secs)) => Err(too_many_requests(secs)),
+    }
+}
+
+// 同时记录到访问日志
+fn parse_uid(req: &HttpRequest, uid: &str) -> Option<i64> {
+    let uid = uid.parse::<i64>().ok()?;
+    logging::set_uid(req, uid);
+    Some(uid)
+}
+
+fn user_json(uid: i64, status: &Status, reason: &str) -> JsonValue {
+    let mut data = object! { uid: uid, status: status.into() };
+
+    if *status != Status::None {
+        data["reason"] = reason.into();
+    }
+
+    data
+}
+
+/*
+GET /v2/users/123456
+Response: {"code": 200, "msg": "查询成功", "data": {"uid": 123456, "status": 1, "reason": "评论区发送解析链接"}}
+*/
+#[get("/users/{uid}")]
+async fn get_user(req: HttpRequest, uid: Path<String>) -> HttpResponse {
+    let uid = match parse_uid(&req, &uid) {
+        Some(uid) => uid,
+        None => return bad_request(),
+    };
+
+    debug!("Recv v2 get user uid={uid}");
+
+    query_result(&req, db::get_user_by_id(uid).await)
+}
+
+/*
+PUT /v2/users/123456/status
+Authorization: Bearer <key>
+Request: {"status": 1, "reason": "..."}
+Response: {"code": 200, "msg": "执行成功", "data": {"uid": 123456, "status": 1, "reason": "..."}}
+*/
+#[put("/users/{uid}/status")]
+async fn put_user_status(req: HttpRequest, uid: Path<String>, data: Bytes) -> HttpResponse {
+    let uid = match parse_uid(&req, &uid) {
+        Some(uid) => uid,
+        None => return bad_request(),
+    };
+
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        None => return bad_request(),
+    };
+
+    let status = match json["status"].as_i8() {
+        Some(s @ 0..=3) => Status::from(s),
+        _ => return bad_request(),
+    };
+
+    let reason = match json["reason"].as_str() {
+        Some(reason) => reason,
+        None => return bad_request(),
+    };
+
+    let action = format!("user.{}", status.display());
+    let target = uid.to_string();
+
+    let exec = match authorize(&req, 0, None, &action, &target).await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    debug!(
+        "Recv v2 set status uid={uid} status={} key#{}",
+        status.display(),
+        exec.id
+    );
+
+    let ok = db::do_op(uid, &status, &exec.role, reason).await;
+    audit::record(
+        Some(&exec),
+        &client_ip(&req),
+        &action,
+        &target,
+        audit::result_of(ok),
+    )
+    .await;
+
+    if !ok {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "内部错误");
+    }
+
+    make_json(
+        StatusCode::OK,
+        object! {
+            code: 200,
+            msg: "执行成功",
+            data: user_json(uid, &status, reason)
+        },
+    )
+}
+
+/*
+GET /v2/users/123456/history?limit=20
+Authorization: Bearer <key>
+Response: {"code": 200, "msg": "查询成功", "data": [{"id": 12, "status": 1, "opRole": "admin", "reason": "...", "timestamp": 1653490177054}]}
+*/
+#[get("/users/{uid}/history")]
+async fn get_user_history(
+    req: HttpRequest,
+    uid: Path<String>,
+    query: Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let uid = match parse_uid(&req, &uid) {
+        Some(uid) => uid,
+        None => return bad_request(),
+    };
+
+    if let Err(resp) = authorize(&req, 0, None, "user.history", &uid.to_string()).await {
+        return resp;
+    }
+
+    let limit = match query.get("limit").map(|s| s.parse::<i64>()) {
+        Some(Ok(limit)) => limit.clamp(1, MAX_HISTORY_LIMIT),
+        Some(_) => return bad_request(),
+        None => DEFAULT_HISTORY_LIMIT,
+    };
+
+    debug!("Recv v2 get history uid={uid}");
+
+    let history: Vec<JsonValue> = db::get_reasons_by_uid(uid, limit)
+        .await
+        .into_iter()
+        .map(|r| {
+            let op = &r.op;
+            object! {
+                id: r.id,
+                status: op.into(),
+                opRole: r.op_role,
+                reason: r.reason,
+                timestamp: r.op_time
+            }
+        })
+        .collect();
+
+    ok(history.into())
+}
+
+/*
+GET /v2/users/123456/profile
+Authorization: Bearer <key>
+Response: {"code": 200, "msg": "查询成功", "data": {"name": "...", "level": 6, "firstSeen": 1653490177054, "lastSeen": 1653490177054}}
+*/
+#[get("/users/{uid}/profile")]
+async fn get_user_profile(req: HttpRequest, uid: Path<String>) -> HttpResponse {
+    let uid = match parse_uid(&req, &uid) {
+        Some(uid) => uid,
+        None => return bad_request(),
+    };
+
+    if let Err(resp) = authorize(&req, 0, None, "user.profile", &uid.to_string()).await {
+        return resp;
+    }
+
+    debug!("Recv v2 get profile uid={uid}");
+
+    match db::get_profile(uid).await {
+        Some(profile) => ok(profile_json(Some(profile))),
+        None => error(StatusCode::NOT_FOUND, "没有该用户的记录"),
+    }
+}
+
+/*
+GET /v2/stats
+Authorization: Bearer <key>
+Response: {"code": 200, "msg": "查询成功", "data": {"blackCount": 1000, "whiteCount": 10, "cache": {"hits": 900, "misses": 100, "hitRate": 0.9, "size": 100}, "security": {"lockedIps": 0, "lockouts": 2}}}
+*/
+#[get("/stats")]
+async fn get_stats(req: HttpRequest) -> HttpResponse {
+    if let Err(resp) = authorize(&req, 0, None, "stats", "").await {
+        return resp;
+    }
+
+    let cache = cache::stats();
+    let lockout = lockout::stats();
+
+    ok(object! {
+        blackCount: db::count_total_by_status(&Status::Black).await,
+        whiteCount: db::count_total_by_status(&Status::White).await,
+        cache: {
+            hits: cache.hits,
+            misses: cache.misses,
+            hitRate: cache.hit_rate(),
+            size: cache.size
+        },
+        security: {
+            lockedIps: lockout.locked,
+            lockouts: lockout.lockouts
+        }
+    })
+}
+
+/*
+GET /v2/keys
+Authorization: Bearer <owner key>
+Response: {"code": 200, "msg": "查询成功", "data": [{"id": 2, "key": "abcd***", "lvl": 1, "role": "..."}]}
+*/
+#[get("/keys")]
+async fn list_keys(req: HttpRequest) -> HttpResponse {
+    let exec = match authorize(&req, 127, Some(db::OWNER_ROLE), "key.list", "").await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    // 只返回key的前4个字符 用于辨认 完整的key只在生成时返回
+    let keys: Vec<JsonValue> = db::list_admin_keys()
+        .await
+        .into_iter()
+        .map(|k| object! { id: k.id, key: redact(&k.key), lvl: k.lvl, role: k.role })
+        .collect();
+
+    audit::record(Some(&exec), &client_ip(&req), "key.list", "", AuditResult::Ok).await;
+
+    ok(keys.into())
+}
+
+/*
+POST /v2/keys
+Authorization: Bearer <owner key>
+Request: {"role": "...", "lvl": 1}
+Response: 201 {"code": 201, "msg": "生成成功", "data": {"id": 2, "key": "..."}}
+*/
+#[post("/keys")]
+async fn create_key(req: HttpRequest, data: Bytes) -> HttpResponse {
+    let json = match get_response_json(data) {
+        Some(json) => json,
+        None => return bad_request(),
+    };
+
+    let role = match json["role"].as_str() {
+        Some(role) => role,
+        None => return bad_request(),
+    };
+
+    // owner的lvl固定为127 只能由owner生成
+    let owner = role == db::OWNER_ROLE;
+
+    let lvl = match &json["lvl"] {
+        JsonValue::Null if owner => 127,
+        JsonValue::Null => 1,
+        v => match v.as_i8() {
+            Some(lvl) if db::valid_key_lvl(role, lvl) => lvl,
+            _ => return bad_request(),
+        },
+    };
+
+    let target = format!("role={role} lvl={lvl}");
+    let required = owner.then_some(db::OWNER_ROLE);
+
+    let exec = match authorize(&req, 127, required, "key.gen", &target).await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    debug!("Recv v2 create key role={role} lvl={lvl}");
+
+    let ret = db::gen_key(lvl, role).await;
+    audit::record(
+        Some(&exec),
+        &client_ip(&req),
+        "key.gen",
+        &target,
+        audit::result_of(ret.is_some()),
+    )
+    .await;
+
+    // 返回id 供DELETE /v2/keys/{id}使用
+    match ret {
+        Some(key) => match db::get_admin_key(&key).await {
+            Some(k) => make_json(
+                StatusCode::CREATED,
+                object! { code: 201, msg: "生成成功", data: { id: k.id, key: key } },
+            ),
+            None => error(StatusCode::INTERNAL_SERVER_ERROR, "内部错误"),
+        },
+        None => error(StatusCode::INTERNAL_SERVER_ERROR, "内部错误"),
+    }
+}
+
+/*
+DELETE /v2/keys/2
+Authorization: Bearer <owner key>
+Response: 204
+通过GET /v2/keys返回的id指定 key本身不出现在URL中
+*/
+#[delete("/keys/{id}")]
+async fn delete_key(req: HttpRequest, id: Path<String>) -> HttpResponse {
+    let id = match id.parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return bad_request(),
+    };
+
+    let revoked = db::get_admin_key_by_id(id).await;
+    let target = format!("key#{id}");
+
+    // 只有owner能吊销owner
+    let required = match &revoked {
+        Some(k) if k.role == db::OWNER_ROLE => Some(db::OWNER_ROLE),
+        _ => None,
+    };
+
+    let exec = match authorize(&req, 127, required, "key.revoke", &target).await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    let revoked = match revoked {
+        Some(k) => k,
+        None => return error(StatusCode::NOT_FOUND, "key不存在"),
+    };
+
+    debug!("Recv v2 delete {target}");
+
+    let ret = db::revoke_admin_key(revoked.id).await;
+    let result = match &ret {
+        Ok(()) => AuditResult::Ok,
+        Err(RevokeError::Failed) => AuditResult::Failed,
+        Err(_) => AuditResult::Denied,
+    };
+    audit::record(Some(&exec), &client_ip(&req), "key.revoke", &target, result).await;
+
+    match ret {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(RevokeError::NotFound) => error(StatusCode::NOT_FOUND, "key不存在"),
+        Err(RevokeError::LastOwner) => error(StatusCode::CONFLICT, "不能吊销最后一个owner"),
+        Err(RevokeError::Failed) => error(StatusCode::INTERNAL_SERVER_ERROR, "内部错误"),
+    }
+}
+
+/*
+DELETE /v2/keys?role=...
+Authorization: Bearer <owner key>
+Response: 204
+*/
+#[delete("/keys")]
+async fn delete_keys_by_role(
+    req: HttpRequest,
+    query: Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let role = match query.get("role") {
+        Some(role) => role,
+        None => return bad_request(),
+    };
+
+    let target = format!("role={role}");
+
+    let exec = match authorize(&req, 127, None, "key.revoke", &target).await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    // owner只能逐个吊销 以免误删全部owner
+    if role == db::OWNER_ROLE {
+        return bad_request();
+    }
+
+    debug!("Recv v2 delete keys role={role}");
+
+    let ok = db::revoke_admin_key_by_role(role).await;
+    audit::record(
+        Some(&exec),
+        &client_ip(&req),
+        "key.revoke",
+        &target,
+        audit::result_of(ok),
+    )
+    .await;
+
+    match ok {
+        true => HttpResponse::NoContent().finish(),
+        false => error(StatusCode::INTERNAL_SERVER_ERROR, "内部错误"),
+    }
+}
+
+/*
+GET /v2/audit?action=key.gen&ip=1.2.3.4&result=denied&before=100&limit=50
+Authorization: Bearer <owner key>
+Response: {"code": 200, "msg": "查询成功", "data": [{"id": 1, "keyId": 1, "role": "owner", "action": "key.gen", "target": "role=helper lvl=1", "ip": "1.2.3.4", "result": "ok", "createdAt": 1653490177054}]}
+*/
+#[get("/audit")]
+async fn list_audit(req: HttpRequest, query: Query<HashMap<String, String>>) -> HttpResponse {
+    let exec = match authorize(&req, 127, Some("owner"), "audit.list", "").await {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+
+    let result = match query.get("result") {
+        Some(s) => match AuditResult::from_name(s) {
+            Some(result) => Some(result),
+            None => return bad_request(),
+        },
+        None => None,
+    };
+
+    let before = match query.get("before").map(|s| s.parse::<i64>()) {
+        Some(Ok(id)) => Some(id),
+        Some(_) => return bad_request(),
+        None => None,
+    };
+
+    let limit = match query.get("limit").map(|s| s.parse::<i64>()) {
+        Some(Ok(limit)) => limit.clamp(1, 500),
+        Some(_) => return bad_request(),
+        None => 50,
+    };
+
+    let filter = AuditFilter {
+        action: query.get("action").cloned(),
+        ip: query.get("ip").cloned(),
+        result,
+        before,
+    };
+
+    let entries = audit_json(db::list_audit(&filter, limit).await);
+    audit::record(Some(&exec), &client_ip(&req), "audit.list", "", AuditResult::Ok).await;
+
+    ok(entries.into())
+}
+
+/*
+GET /v2/abuse?minKeys=5&minIps=20&minUids=10&limit=50
+Authorization: Bearer <key>
+Response: {"code": 200, "msg": "查询成功", "data": {"uids": [{"uid": 123456, "keys": 6, "ips": 3, "lastSeen": 1653490177054, "status": 3}], "ips": [{"ip": "1.2.3.4", "uids": 12, "lastSeen": 1653490177054}]}}
+*/
+#[get("/abuse")]
+async fn abuse_report(req: HttpRequest, query: Query<HashMap<String, String>>) -> HttpResponse {
+    if let Err(resp) = authorize(&req, 127, None, "abuse.report", "").await {
+        return resp;
+    }
+
+    let mut thresholds = [None; 3];
+
+    for (i, name) in ["minKeys", "minIps", "minUids"].iter().enumerate() {
+        thresholds[i] = match query.get(*name).map(|s| s.parse::<i64>()) {
+            Some(Ok(n)) => Some(n),
+            Some(_) => return bad_request(),
+            None => None,
+        };
+    }
+
+    let limit = match query.get("limit").map(|s| s.parse::<i64>()) {
+        Some(Ok(limit)) => limit.clamp(1, 500),
+        Some(_) => return bad_request(),
+        None => 50,
+    };
+
+    let [min_keys, min_ips, min_uids] = thresholds;
+
+    ok(abuse_json(min_keys, min_ips, min_uids, limit).await)
+}
+
+pub fn scope() -> Scope {
+    web::scope("/v2")
+        .service(get_user)
+        .service(put_user_status)
+        .service(get_user_history)
+        .service(get_user_profile)
+        .service(abuse_report)
+        .service(get_stats)
+        .service(list_keys)
+        .service(create_key)
+        .service(delete_key)
+        .service(delete_keys_by_role)
+        .service(list_audit)
+}
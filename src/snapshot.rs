@@ -0,0 +1,215 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use actix_web::web::Bytes;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use json::{object, JsonValue};
+use log::{error, info};
+use rand::Rng;
+
+use crate::{configs, db, enums::Status, utils};
+
+pub struct Snapshot {
+    // 生成快照时最新的reasons id
+    pub cursor: i64,
+    pub body: Bytes,
+    // 对body的Ed25519签名 hex编码
+    pub signature: String,
+    pub public_key: String,
+}
+
+lazy_static::lazy_static! {
+    static ref LATEST: RwLock<Option<Arc<Snapshot>>> = RwLock::new(None);
+}
+
+pub fn latest() -> Option<Arc<Snapshot>> {
+    LATEST.read().unwrap().clone()
+}
+
+/// 签名私钥文件保存32字节种子的hex编码
+pub fn load_signing_key(path: &str) -> Result<SigningKey, String> {
+    let s = fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+
+    let seed: [u8; 32] = utils::from_hex(s.trim())
+        .and_then(|b| b.try_into().ok())
+        .ok_or(format!("{path} is not a hex encoded 32 bytes seed"))?;
+
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// 生成新的签名私钥写入文件 返回公钥的hex编码 文件已存在时不覆盖
+pub fn gen_signing_key(path: &str) -> Result<String, String> {
+    let seed: [u8; 32] = rand::thread_rng().gen();
+
+    // 只有所有者可以读写
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!("{path} already exists, remove it first to generate a new key"),
+            _ => format!("cannot create {path}: {e}"),
+        })?;
+
+    file.write_all(utils::to_hex(&seed).as_bytes())
+        .map_err(|e| format!("cannot write {path}: {e}"))?;
+
+    let key = SigningKey::from_bytes(&seed);
+    Ok(utils::to_hex(key.verifying_key().as_bytes()))
+}
+
+/// 校验快照签名 所有参数均为snapshot接口返回的原始内容
+pub fn verify(body: &[u8], signature_hex: &str, public_key_hex: &str) -> bool {
+    let signature = match utils::from_hex(signature_hex.trim()) {
+        Some(b) => match Signature::from_slice(&b) {
+            Ok(s) => s,
+            _ => return false,
+        },
+        None => return false,
+    };
+
+    let public_key: [u8; 32] = match utils::from_hex(public_key_hex.trim()).and_then(|b| b.try_into().ok()) {
+        Some(k) => k,
+        None => return false,
+    };
+
+    match VerifyingKey::from_bytes(&public_key) {
+        Ok(key) => key.verify(body, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+async fn build(key: &SigningKey) -> Snapshot {
+    let cursor = db::get_last_reason_id().await;
+
+    sign(key, cursor, &db::get_marked_users().await)
+}
+
+/// 生成快照内容并签名 灰名单用户不写入快照
+fn sign(key: &SigningKey, cursor: i64, marked: &[(i64, Status)]) -> Snapshot {
+    let users: Vec<JsonValue> = marked
+        .iter()
+        .filter(|(_, status)| *status != Status::Gray)
+        .map(|(uid, status)| json::array![*uid, status.into_v1()])
+        .collect();
+
+    let body = object! {
+        version: 1,
+        generatedAt: utils::current_milliseconds(),
+        cursor: cursor,
+        count: users.len(),
+        users: users
+    }
+    .dump();
+
+    let signature = key.sign(body.as_bytes());
+
+    Snapshot {
+        cursor,
+        body: Bytes::from(body),
+        signature: utils::to_hex(&signature.to_bytes()),
+        public_key: utils::to_hex(key.verifying_key().as_bytes()),
+    }
+}
+
+pub async fn run_job() {
+    let config = configs::get();
+    let path = &config.snapshot.signing_key_file;
+
+    if path.is_empty() {
+        return;
+    }
+
+    let key = match load_signing_key(path) {
+        Ok(key) => key,
+        Err(e) => {
+            error!("Snapshot disabled: {e}");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.snapshot.interval_secs));
+
+    loop {
+        interval.tick().await;
+
+        // 没有新的变更时不重新生成
+        let cursor = db::get_last_reason_id().await;
+        if latest().map(|s| s.cursor) == Some(cursor) {
+            continue;
+        }
+
+        let snapshot = build(&key).await;
+        info!("Snapshot generated at cursor {}", snapshot.cursor);
+        *LATEST.write().unwrap() = Some(Arc::new(snapshot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    fn signed() -> (Snapshot, String) {
+        let n = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("brbs-snapshot-{}-{n}.key", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let public_key = gen_signing_key(path).unwrap();
+        // 已存在时不覆盖
+        assert!(gen_signing_key(path).is_err());
+
+        let key = load_signing_key(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let marked = [(1, Status::Black), (2, Status::White), (3, Status::Gray)];
+        (sign(&key, 10, &marked), public_key)
+    }
+
+    #[test]
+    fn signed_snapshot_verifies() {
+        let (snapshot, public_key) = signed();
+
+        assert_eq!(snapshot.public_key, public_key);
+        assert!(verify(&snapshot.body, &snapshot.signature, &public_key));
+
+        let json = json::parse(std::str::from_utf8(&snapshot.body).unwrap()).unwrap();
+        assert_eq!(json["cursor"], 10);
+        assert_eq!(json["count"], 2);
+        assert_eq!(json["users"], json::array![[1, 1], [2, 2]]);
+    }
+
+    #[test]
+    fn tampered_snapshot_is_rejected() {
+        let (snapshot, public_key) = signed();
+
+        let mut body = snapshot.body.to_vec();
+        let i = body.len() - 2;
+        body[i] ^= 1;
+        assert!(!verify(&body, &snapshot.signature, &public_key));
+
+        let mut signature = snapshot.signature.clone().into_bytes();
+        signature[0] = if signature[0] == b'0' { b'1' } else { b'0' };
+        let signature = String::from_utf8(signature).unwrap();
+        assert!(!verify(&snapshot.body, &signature, &public_key));
+
+        let (_, other_key) = signed();
+        assert!(!verify(&snapshot.body, &snapshot.signature, &other_key));
+
+        assert!(!verify(&snapshot.body, "not hex", &public_key));
+        assert!(!verify(&snapshot.body, &snapshot.signature, "00"));
+    }
+}
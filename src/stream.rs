@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use json::object;
+use log::debug;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{configs, db, structs::Reason};
+
+// 每次从数据库读取的记录数
+const BATCH_SIZE: i64 = 500;
+
+lazy_static::lazy_static! {
+    // 只用于唤醒订阅者 变更内容统一从数据库读取 保证断线续传和多实例下的顺序一致
+    static ref NOTIFY: broadcast::Sender<i64> = broadcast::channel(16).0;
+}
+
+pub fn publish(id: i64) {
+    // 没有订阅者时发送失败 可以忽略
+    let _ = NOTIFY.send(id);
+}
+
+fn event(r: &Reason) -> Bytes {
+    let op = &r.op;
+    let data = object! {
+        id: r.id,
+        uid: r.uid,
+        op: op.into_v1(),
+        opTime: r.op_time
+    }
+    .dump();
+
+    Bytes::from(format!("id: {}\nevent: change\ndata: {data}\n\n", r.id))
+}
+
+/// 推送id大于last的全部变更 返回最后推送的id 客户端断开时返回None
+async fn flush(tx: &mpsc::Sender<Bytes>, mut last: i64) -> Option<i64> {
+    loop {
+        let reasons = db::get_reasons_after(last, BATCH_SIZE).await;
+
+        for r in &reasons {
+            tx.send(event(r)).await.ok()?;
+            last = r.id;
+        }
+
+        if (reasons.len() as i64) < BATCH_SIZE {
+            return Some(last);
+        }
+    }
+}
+
+async fn run(tx: mpsc::Sender<Bytes>, since: Option<i64>) {
+    // 先订阅再读取 避免读取期间的变更丢失
+    let mut rx = NOTIFY.subscribe();
+
+    let config = configs::get();
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(config.stream.heartbeat_secs));
+    let mut poll = tokio::time::interval(Duration::from_secs(config.stream.poll_interval_secs));
+
+    // 未指定起点时只推送之后的变更
+    let mut last = match since {
+        Some(id) => id,
+        None => db::get_last_reason_id().await,
+    };
+
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                if tx.send(Bytes::from_static(b": ping\n\n")).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            _ = poll.tick() => {}
+            ret = rx.recv() => {
+                if let Err(broadcast::error::RecvError::Closed) = ret {
+                    break;
+                }
+            }
+        }
+
+        last = match flush(&tx, last).await {
+            Some(id) => id,
+            None => break,
+        };
+    }
+
+    debug!("Change stream closed at {last}");
+}
+
+/// 返回推送SSE数据的通道 客户端断开后后台任务自动退出
+pub fn subscribe(since: Option<i64>) -> mpsc::Receiver<Bytes> {
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(run(tx, since));
+    rx
+}
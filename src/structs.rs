@@ -1,7 +1,8 @@
-use crate::enums::Status;
+use crate::enums::{AuditResult, DeliveryStatus, Status};
 
 #[derive(Debug, Clone)]
 pub struct Reason {
+    pub id: i64,
     pub uid: i64,
     pub op: Status,
     pub op_role: String,
@@ -14,6 +15,111 @@ pub struct User {
     pub uid: i64,
     pub status: Status,
     pub last_reason: Option<String>,
+    // 最近一条reasons记录的id 没有记录时为0
+    pub last_change: i64,
+}
+
+/// 状态和被拉黑次数 由一次查询得到
+#[derive(Debug, Clone)]
+pub struct UserSummary {
+    pub uid: i64,
+    pub status: Status,
+    pub last_reason: Option<String>,
+    pub black_times: i64,
+    // 最近一次变更的时间(毫秒) 没有记录时为None
+    pub last_op_time: Option<i64>,
+    // 最近一条reasons记录的id 没有记录时为0
+    pub last_change: i64,
+}
+
+/// myinfo接口返回的用户信息
+#[derive(Debug, Clone, Default)]
+pub struct BiliProfile {
+    pub uid: i64,
+    pub name: String,
+    pub level: i32,
+    // 大会员是否有效
+    pub vip: bool,
+    pub face: String,
+}
+
+/// 最近一次通过access_key查询到的用户信息
+#[derive(Debug, Clone)]
+pub struct UserProfile {
+    pub uid: i64,
+    pub name: String,
+    pub level: i32,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// 一段时间内同一uid使用过的access_key和来源IP数量
+#[derive(Debug, Clone)]
+pub struct UidSightings {
+    pub uid: i64,
+    pub keys: i64,
+    pub ips: i64,
+    pub last_seen: i64,
+}
+
+/// 一段时间内同一IP查询过的uid数量
+#[derive(Debug, Clone)]
+pub struct IpSightings {
+    pub ip: String,
+    pub uids: i64,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminKey {
+    pub id: i64,
+    pub key: String,
+    pub lvl: i8,
+    pub role: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    // 操作者key的id和role 未知key时为None
+    pub key_id: Option<i64>,
+    pub role: Option<String>,
+    pub action: String,
+    pub target: String,
+    pub ip: String,
+    pub result: AuditResult,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub action: Option<String>,
+    pub ip: Option<String>,
+    pub result: Option<AuditResult>,
+    // 只返回id小于该值的记录 用于翻页
+    pub before: Option<i64>,
+}
+
+/// 状态变更时写入的待投递记录 与变更在同一事务中写入
+#[derive(Debug, Clone)]
+pub struct PendingWebhook {
+    pub subscription: String,
+    pub url: String,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription: String,
+    pub url: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt: i64,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
 }
 
 // impl User {
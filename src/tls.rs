@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use log::{error, info};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig,
+};
+use rustls_pemfile::Item;
+
+use crate::configs::{self, TlsConfig};
+
+lazy_static::lazy_static! {
+    // 每次握手时读取 更新后新连接立即使用新证书
+    static ref CERT: RwLock<Option<Arc<CertifiedKey>>> = RwLock::new(None);
+}
+
+struct Resolver;
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        CERT.read().unwrap().clone()
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).map_err(|e| format!("cannot read {path}: {e}"))?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {path}"));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+// 支持PKCS#8、RSA和EC私钥 使用文件中的第一个
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+    let mut reader = BufReader::new(file);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|e| format!("cannot read {path}: {e}"))? {
+            Some(Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(format!("no private key found in {path}")),
+        }
+    }
+}
+
+fn load(config: &TlsConfig) -> Result<Arc<CertifiedKey>, String> {
+    let certs = load_certs(&config.cert_file)?;
+    let key = load_key(&config.key_file)?;
+
+    let key = sign::any_supported_type(&key).map_err(|_| format!("unsupported private key in {}", config.key_file))?;
+
+    Ok(Arc::new(CertifiedKey::new(certs, key)))
+}
+
+/// 读取证书并生成rustls配置 未配置证书时返回None
+pub fn server_config() -> Result<Option<ServerConfig>, String> {
+    let config = configs::get();
+
+    if !config.tls.enabled() {
+        return Ok(None);
+    }
+
+    *CERT.write().unwrap() = Some(load(&config.tls)?);
+
+    let tls = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(Resolver));
+
+    Ok(Some(tls))
+}
+
+/// 重新读取证书 失败时继续使用原证书 启动时没有启用HTTPS则不处理
+pub fn reload() -> bool {
+    if CERT.read().unwrap().is_none() {
+        return false;
+    }
+
+    let config = configs::get();
+
+    match load(&config.tls) {
+        Ok(cert) => {
+            *CERT.write().unwrap() = Some(cert);
+            info!("Reloaded TLS certificate from {}", config.tls.cert_file);
+            true
+        }
+        Err(e) => {
+            error!("Cannot reload TLS certificate, keep the current one: {e}");
+            false
+        }
+    }
+}
+
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let cert = std::fs::metadata(&config.cert_file).and_then(|m| m.modified()).ok()?;
+    let key = std::fs::metadata(&config.key_file).and_then(|m| m.modified()).ok()?;
+    Some((cert, key))
+}
+
+/// 定期检查证书和私钥文件的修改时间 变化时重新加载
+pub async fn run_watcher() {
+    let mut last = modified(&configs::get().tls);
+
+    loop {
+        let secs = configs::get().tls.watch_interval_secs;
+
+        // 为0时等待重新加载配置后再检查
+        tokio::time::sleep(Duration::from_secs(if secs == 0 { 60 } else { secs })).await;
+
+        let config = configs::get();
+        if config.tls.watch_interval_secs == 0 || !config.tls.enabled() {
+            continue;
+        }
+
+        let current = modified(&config.tls);
+
+        if current.is_some() && current != last {
+            last = current;
+            reload();
+        }
+    }
+}
@@ -17,4 +17,29 @@ pub fn get_response_json(data: Bytes) -> Option<JsonValue> {
     };
 
     json::parse(&parse_data).ok()
-}
\ No newline at end of file
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// 日志中只保留access_key的前4个字符
+pub fn redact(key: &str) -> String {
+    let prefix: String = key.chars().take(4).collect();
+
+    match key.chars().count() > 8 {
+        true => format!("{prefix}***"),
+        false => "***".to_owned(),
+    }
+}
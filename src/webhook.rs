@@ -0,0 +1,263 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use json::object;
+use log::{error, info, warn};
+use sha2::Sha256;
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::{
+    configs, db,
+    enums::{DeliveryStatus, Status},
+    structs::{PendingWebhook, Reason, WebhookDelivery},
+    utils,
+};
+
+// 单次扫描最多投递的记录数
+const BATCH_SIZE: i64 = 100;
+
+// 重试间隔上限(毫秒)
+const MAX_RETRY_DELAY: i64 = 60 * 60 * 1000;
+
+// 租约在请求超时之外额外保留的时间(毫秒) 实例在投递中退出时 到期后由其他实例重新投递
+const LEASE_MARGIN: i64 = 60 * 1000;
+
+lazy_static::lazy_static! {
+    // 停机时唤醒等待下一次扫描的后台任务
+    static ref STOP: Notify = Notify::new();
+}
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+fn event_name(status: &Status) -> &'static str {
+    match status {
+        Status::None => "none",
+        Status::Black => "black",
+        Status::White => "white",
+        Status::Gray => "gray",
+    }
+}
+
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+
+    utils::to_hex(&mac.finalize().into_bytes())
+}
+
+/// 第n次失败后等待base * 2^(n-1) 不超过MAX_RETRY_DELAY
+fn retry_delay(base_secs: u64, attempts: i32) -> i64 {
+    let base = i64::try_from(base_secs).unwrap_or(i64::MAX).saturating_mul(1000);
+
+    base.saturating_mul(1 << (attempts - 1).clamp(0, 20))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// 订阅了该事件的webhook各一条待投递记录 状态没有变化时不通知
+pub fn pending(change: &Reason, from: &Status) -> Vec<PendingWebhook> {
+    if change.op == *from {
+        return Vec::new();
+    }
+
+    let config = configs::get();
+    let event = event_name(&change.op);
+
+    let payload = object! {
+        id: change.id,
+        event: event,
+        uid: change.uid,
+        from: event_name(from),
+        to: event,
+        opRole: change.op_role.as_str(),
+        reason: change.reason.as_str(),
+        timestamp: change.op_time
+    }
+    .dump();
+
+    config
+        .webhooks
+        .subscriptions
+        .iter()
+        .filter(|s| s.accepts(event))
+        .map(|sub| PendingWebhook {
+            subscription: sub.name.clone(),
+            url: sub.url.clone(),
+            payload: payload.clone(),
+        })
+        .collect()
+}
+
+async fn deliver(client: &reqwest::Client, delivery: &WebhookDelivery) -> Result<(), String> {
+    let config = configs::get();
+
+    // 使用当前配置中的密钥 轮换密钥后重试的请求也能通过校验
+    let sub = config
+        .webhooks
+        .subscriptions
+        .iter()
+        .find(|s| s.name == delivery.subscription)
+        .ok_or("subscription removed")?;
+
+    let event = json::parse(&delivery.payload)
+        .ok()
+        .and_then(|j| j["event"].as_str().map(|s| s.to_owned()))
+        .unwrap_or_default();
+
+    let ret = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Brbs-Event", event)
+        .header("X-Brbs-Delivery", delivery.id.to_string())
+        .header(
+            "X-Brbs-Signature",
+            format!("sha256={}", sign(&sub.secret, &delivery.payload)),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match ret.status() {
+        s if s.is_success() => Ok(()),
+        s => Err(format!("unexpected status {s}")),
+    }
+}
+
+/// 投递一批到期的webhook 返回取出的记录数 小于BATCH_SIZE时说明已没有到期的记录
+async fn process(client: &reqwest::Client) -> i64 {
+    let config = configs::get();
+    let mut count = 0;
+
+    while count < BATCH_SIZE {
+        // 多个实例共用数据库时 每条记录只会被一个实例取出 租约到期前其他实例不会重复投递
+        let now = utils::current_milliseconds();
+        let lease = now + config.webhooks.timeout_secs as i64 * 1000 + LEASE_MARGIN;
+
+        let mut delivery = match db::claim_due_webhook(now, lease).await {
+            Some(delivery) => delivery,
+            None => break,
+        };
+        count += 1;
+
+        let ret = deliver(client, &delivery).await;
+
+        delivery.attempts += 1;
+        delivery.updated_at = utils::current_milliseconds();
+
+        match ret {
+            Ok(_) => {
+                info!(
+                    "Delivered webhook {} to {}",
+                    delivery.id, delivery.subscription
+                );
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.last_error = None;
+            }
+            Err(e) if delivery.attempts as u32 >= config.webhooks.max_attempts => {
+                error!(
+                    "Give up webhook {} to {} after {} attempts: {e}",
+                    delivery.id, delivery.subscription, delivery.attempts
+                );
+                delivery.status = DeliveryStatus::Failed;
+                delivery.last_error = Some(e);
+            }
+            Err(e) => {
+                let delay = retry_delay(config.webhooks.retry_base_secs, delivery.attempts);
+
+                warn!(
+                    "Cannot deliver webhook {} to {} (attempt {}), retry in {}s: {e}",
+                    delivery.id,
+                    delivery.subscription,
+                    delivery.attempts,
+                    delay / 1000
+                );
+                delivery.next_attempt = delivery.updated_at + delay;
+                delivery.last_error = Some(e);
+            }
+        }
+
+        db::update_webhook(&delivery).await;
+    }
+
+    count
+}
+
+fn build_client(timeout_secs: u64) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .unwrap()
+}
+
+/// 停机前先停止后台任务并等待进行中的投递结束 再投递所有已到期的webhook 超时后剩余的留到下次启动
+pub async fn flush(worker: JoinHandle<()>) {
+    let config = configs::get();
+    let client = build_client(config.webhooks.timeout_secs);
+    let timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+
+    STOPPING.store(true, Ordering::SeqCst);
+    STOP.notify_one();
+
+    let ret = tokio::time::timeout(timeout, async {
+        if let Err(e) = worker.await {
+            error!("Webhook worker failed with error: {e}");
+        }
+        // 失败的记录会推迟到之后重试 循环一定会结束
+        while process(&client).await == BATCH_SIZE {}
+    })
+    .await;
+
+    if ret.is_err() {
+        warn!("Pending webhooks not delivered in {}s, will retry on next start", timeout.as_secs());
+    }
+}
+
+pub async fn run_worker() {
+    let mut timeout_secs = configs::get().webhooks.timeout_secs;
+    let mut client = build_client(timeout_secs);
+
+    while !STOPPING.load(Ordering::SeqCst) {
+        let config = configs::get();
+
+        if config.webhooks.timeout_secs != timeout_secs {
+            timeout_secs = config.webhooks.timeout_secs;
+            client = build_client(timeout_secs);
+        }
+
+        process(&client).await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.webhooks.poll_interval_secs)) => {}
+            _ = STOP.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(sign("other", "what do ya want for nothing?"), sign("Jefe", "what do ya want for nothing?"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_until_capped() {
+        assert_eq!(retry_delay(10, 1), 10_000);
+        assert_eq!(retry_delay(10, 2), 20_000);
+        assert_eq!(retry_delay(10, 3), 40_000);
+        assert_eq!(retry_delay(10, 9), 2_560_000);
+        assert_eq!(retry_delay(10, 10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(10, 1000), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u64::MAX, 1), MAX_RETRY_DELAY);
+    }
+}
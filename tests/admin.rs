@@ -0,0 +1,104 @@
+use actix_web::{
+    http::{header, StatusCode},
+    test::{self, TestRequest},
+};
+
+mod common;
+
+fn bearer(key: &str) -> (header::HeaderName, String) {
+    (header::AUTHORIZATION, format!("Bearer {key}"))
+}
+
+#[actix_web::test]
+async fn list_keys_masks_keys_and_is_audited() {
+    let owner = common::prepare(&common::sqlite_url()).await;
+
+    let resp = common::call(TestRequest::get().uri("/v2/keys").insert_header(bearer(&owner))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json = common::parse(&test::read_body(resp).await);
+    let keys = &json["data"];
+    assert!(!keys.is_empty());
+
+    for k in keys.members() {
+        let key = k["key"].as_str().unwrap();
+        assert!(key.ends_with("***"), "{key}");
+        assert!(key.len() < owner.len());
+        assert!(k["id"].is_number() && k["lvl"].is_number() && k["role"].is_string());
+    }
+
+    let resp = common::call(
+        TestRequest::get()
+            .uri("/v2/audit?result=ok&limit=500")
+            .insert_header(bearer(&owner)),
+    )
+    .await;
+    let json = common::parse(&test::read_body(resp).await);
+    assert!(json["data"].members().any(|e| e["action"] == "key.list"));
+
+    // 查询审计日志本身也会记录
+    let resp = common::call(
+        TestRequest::get()
+            .uri("/v2/audit?action=audit.list")
+            .insert_header(bearer(&owner)),
+    )
+    .await;
+    let json = common::parse(&test::read_body(resp).await);
+    assert!(!json["data"].is_empty());
+}
+
+#[actix_web::test]
+async fn list_keys_requires_owner() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let resp = common::call(TestRequest::get().uri("/v2/keys")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn wrong_keys_lock_out_the_ip() {
+    let owner = common::prepare(&common::sqlite_url()).await;
+
+    let from = |ip: &str, key: &str| {
+        TestRequest::get()
+            .uri("/v2/keys")
+            .peer_addr(format!("{ip}:40000").parse().unwrap())
+            .insert_header(bearer(key))
+    };
+
+    // 默认连续错误5次后锁定60秒
+    for _ in 0..4 {
+        let resp = common::call(from("10.39.0.1", "wrong-key")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let resp = common::call(from("10.39.0.1", "wrong-key")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "60");
+
+    // 锁定期间正确的key也被拒绝 不影响其他IP
+    let resp = common::call(from("10.39.0.1", &owner)).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let resp = common::call(from("10.39.0.2", &owner)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn correct_key_resets_failures() {
+    let owner = common::prepare(&common::sqlite_url()).await;
+
+    let from = |key: &str| {
+        TestRequest::get()
+            .uri("/v2/keys")
+            .peer_addr("10.39.1.1:40000".parse().unwrap())
+            .insert_header(bearer(key))
+    };
+
+    for _ in 0..2 {
+        for _ in 0..4 {
+            assert_eq!(common::call(from("wrong-key")).await.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(common::call(from(&owner)).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+use json::object;
+
+/// 每个测试使用单独的数据库 不使用wal 直接复制数据库文件即可作为备份
+fn workspace(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("brbs-backup-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let config = object! {
+        database: { url: format!("sqlite://{}/black.db?mode=rwc", dir.display()), journalMode: "delete" }
+    };
+    fs::write(dir.join("config.json"), config.dump()).unwrap();
+
+    dir
+}
+
+fn run(bin: &str, dir: &Path, args: &[&str]) -> Output {
+    Command::new(bin)
+        .args(args)
+        .env("BRBS_CONFIG", dir.join("config.json"))
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn admin(dir: &Path, args: &[&str]) -> Output {
+    run(env!("CARGO_BIN_EXE_brbs-admin"), dir, args)
+}
+
+fn restore(dir: &Path, file: &Path) -> Output {
+    run(env!("CARGO_BIN_EXE_brbs-rs"), dir, &["restore", file.to_str().unwrap()])
+}
+
+fn status(dir: &Path, uid: &str) -> String {
+    String::from_utf8_lossy(&admin(dir, &["user", "get", uid]).stdout).into_owned()
+}
+
+fn pre_restore_files(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("black.db.pre-restore-"))
+        .count()
+}
+
+#[test]
+fn restore_replaces_database() {
+    let dir = workspace("restore");
+
+    assert!(admin(&dir, &["user", "set", "1", "black", "备份前"]).status.success());
+    let backup = dir.join("backup.db");
+    fs::copy(dir.join("black.db"), &backup).unwrap();
+
+    assert!(admin(&dir, &["user", "set", "1", "white", "备份后"]).status.success());
+    assert!(status(&dir, "1").contains("status: white"));
+
+    let out = restore(&dir, &backup);
+    assert!(out.status.success(), "{out:?}");
+    assert!(status(&dir, "1").contains("status: black"));
+    assert_eq!(pre_restore_files(&dir), 1);
+    assert!(!dir.join("black.db.restore-tmp").exists());
+}
+
+#[test]
+fn invalid_backup_keeps_database() {
+    let dir = workspace("invalid");
+
+    assert!(admin(&dir, &["user", "set", "2", "black", "测试"]).status.success());
+
+    let backup = dir.join("backup.db");
+    fs::write(&backup, "not a database").unwrap();
+    assert!(!restore(&dir, &backup).status.success());
+
+    // 不存在的备份文件
+    assert!(!restore(&dir, &dir.join("missing.db")).status.success());
+
+    assert!(status(&dir, "2").contains("status: black"));
+    assert_eq!(pre_restore_files(&dir), 0);
+}
+
+#[test]
+fn restore_refuses_running_server() {
+    let dir = workspace("locked");
+
+    assert!(admin(&dir, &["user", "set", "3", "black", "测试"]).status.success());
+    let backup = dir.join("backup.db");
+    fs::copy(dir.join("black.db"), &backup).unwrap();
+
+    // 模拟运行中的服务器持有的锁
+    let lock = File::create(dir.join("black.db.lock")).unwrap();
+    lock.lock().unwrap();
+
+    let out = restore(&dir, &backup);
+    assert!(!out.status.success());
+    assert_eq!(pre_restore_files(&dir), 0);
+
+    lock.unlock().unwrap();
+    assert!(restore(&dir, &backup).status.success());
+}
@@ -0,0 +1,263 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::{
+    dev::ServerHandle,
+    http::{header, StatusCode},
+    test,
+    web::{self, Data},
+    App, HttpRequest, HttpResponse, HttpServer,
+};
+
+use brbs_rs::{
+    bili_requests::{BiliClient, FakeBiliClient, HttpBiliClient},
+    configs::{self, BiliApp},
+    db,
+    enums::{BiliApi, Status},
+    routing,
+};
+
+mod common;
+
+/// 通过X-Access-Key查询 返回HTTP状态码和响应体
+async fn query(bili: Arc<dyn BiliClient>, path: &str, key: &str) -> (StatusCode, json::JsonValue) {
+    common::prepare(&common::sqlite_url()).await;
+
+    let app = test::init_service(App::new().app_data(Data::from(bili)).configure(routing::configure)).await;
+    let req = test::TestRequest::get()
+        .uri(path)
+        .insert_header(("X-Access-Key", key))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status();
+
+    (status, common::parse(&test::read_body(resp).await))
+}
+
+type Hits = Arc<Mutex<Vec<String>>>;
+
+/// 模拟B站接口 每个请求都返回相同的响应 同时记录请求的query string
+async fn start_upstream(status: u16, body: &'static str) -> (String, Hits, ServerHandle) {
+    let hits = Hits::default();
+    let recorder = hits.clone();
+
+    let server = HttpServer::new(move || {
+        let recorder = recorder.clone();
+        App::new().default_service(web::to(move |req: HttpRequest| {
+            recorder.lock().unwrap().push(req.query_string().to_owned());
+            async move {
+                HttpResponse::build(StatusCode::from_u16(status).unwrap())
+                    .content_type("application/json")
+                    .body(body)
+            }
+        }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+
+    let base_url = format!("http://{}", server.addrs()[0]);
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    (base_url, hits, handle)
+}
+
+fn count(hits: &Hits) -> usize {
+    hits.lock().unwrap().len()
+}
+
+fn http_client(base_url: &str, breaker_threshold: u32, breaker_cooldown_secs: u64) -> Arc<dyn BiliClient> {
+    let mut config = configs::get().bili.clone();
+    config.apps = vec![BiliApp {
+        name: "android".to_owned(),
+        api: BiliApi::App,
+        base_url: base_url.to_owned(),
+        app_key: "appkey".to_owned(),
+        app_sec: "appsec".to_owned(),
+        client: "android".to_owned(),
+    }];
+    config.max_retries = 0;
+    config.breaker_threshold = breaker_threshold;
+    config.breaker_cooldown_secs = breaker_cooldown_secs;
+
+    Arc::new(HttpBiliClient::new(&config))
+}
+
+#[actix_web::test]
+async fn query_status_by_key() {
+    let owner = common::prepare(&common::sqlite_url()).await;
+    assert!(!owner.is_empty());
+
+    assert!(db::do_op(41001, &Status::Black, "admin", "测试").await);
+
+    let bili = Arc::new(FakeBiliClient::new().with_key("black-key", 41001).with_key("normal-key", 41002));
+
+    let (status, json) = query(bili.clone(), "/query/status", "black-key").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["code"], 200);
+    assert_eq!(json["data"]["uid"], 41001);
+    assert_eq!(json["data"]["status"], 1);
+    assert_eq!(json["data"]["reason"], "测试");
+
+    // 共享缓存需要按access_key区分
+    let app = test::init_service(App::new().app_data(Data::from(bili.clone() as Arc<dyn BiliClient>)).configure(routing::configure)).await;
+    let req = test::TestRequest::get()
+        .uri("/query/status")
+        .insert_header(("X-Access-Key", "black-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(header::VARY).unwrap(), "X-Access-Key");
+
+    let (_, json) = query(bili.clone(), "/query/status", "normal-key").await;
+    assert_eq!(json["data"]["uid"], 41002);
+    assert_eq!(json["data"]["status"], 0);
+
+    let (_, json) = query(bili, "/query/times", "black-key").await;
+    assert_eq!(json["data"]["blackTimes"], 1);
+}
+
+#[actix_web::test]
+async fn query_full_by_key() {
+    common::prepare(&common::sqlite_url()).await;
+
+    assert!(db::do_op(41101, &Status::White, "admin", "测试").await);
+
+    let bili = Arc::new(FakeBiliClient::new().with_key("white-key", 41101));
+    let (_, json) = query(bili, "/query/full", "white-key").await;
+
+    assert_eq!(json["data"]["uid"], 41101);
+    assert_eq!(json["data"]["status"], 2);
+    assert_eq!(json["data"]["blackTimes"], 0);
+    assert!(json["data"]["expiry"].is_null());
+}
+
+#[actix_web::test]
+async fn unknown_key_is_invalid() {
+    let bili = Arc::new(FakeBiliClient::new());
+    let (status, json) = query(bili, "/query/status", "unknown-key").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["code"], 400);
+}
+
+#[actix_web::test]
+async fn upstream_profile() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (base_url, hits, handle) = start_upstream(200, r#"{"code": 0, "data": {"mid": 41201, "name": "test"}}"#).await;
+    let (_, json) = query(http_client(&base_url, 5, 60), "/query/status", "upstream-key").await;
+
+    assert_eq!(json["data"]["uid"], 41201);
+    assert_eq!(count(&hits), 1);
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn upstream_errors() {
+    common::prepare(&common::sqlite_url()).await;
+
+    // access_key过期
+    let (base_url, _, handle) = start_upstream(200, r#"{"code": -101, "message": "账号未登录"}"#).await;
+    let (status, json) = query(http_client(&base_url, 5, 60), "/query/status", "expired-key").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["code"], 400);
+    handle.stop(false).await;
+
+    // 风控拦截
+    let (base_url, _, handle) = start_upstream(412, "").await;
+    let (status, json) = query(http_client(&base_url, 5, 60), "/query/status", "rejected-key").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(json["code"], 502);
+    handle.stop(false).await;
+
+    let (base_url, _, handle) = start_upstream(500, "").await;
+    let (status, json) = query(http_client(&base_url, 5, 60), "/query/status", "unavailable-key").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["code"], 503);
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn breaker_opens_after_failures() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (base_url, hits, handle) = start_upstream(500, "").await;
+    let bili = http_client(&base_url, 2, 60);
+
+    for _ in 0..2 {
+        let (status, _) = query(bili.clone(), "/query/status", "breaker-key").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+    assert_eq!(count(&hits), 2);
+
+    // 熔断期间不再请求B站
+    let (status, json) = query(bili, "/query/status", "breaker-key").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["code"], 503);
+    assert_eq!(count(&hits), 2);
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn breaker_half_open_probe() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (base_url, hits, handle) = start_upstream(500, "").await;
+    let bili = http_client(&base_url, 2, 1);
+
+    for _ in 0..3 {
+        query(bili.clone(), "/query/status", "probe-key").await;
+    }
+    assert_eq!(count(&hits), 2);
+
+    // 熔断结束后放行一个探测请求 失败后立即重新熔断
+    actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+    let (status, _) = query(bili.clone(), "/query/status", "probe-key").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(count(&hits), 3);
+
+    query(bili, "/query/status", "probe-key").await;
+    assert_eq!(count(&hits), 3);
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn unknown_app_is_rejected() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (base_url, hits, handle) = start_upstream(200, r#"{"code": 0, "data": {"mid": 41301}}"#).await;
+    let (status, json) = query(http_client(&base_url, 5, 60), "/query/status?app=unknown", "app-key").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["code"], 400);
+    assert_eq!(count(&hits), 0);
+
+    let (_, json) = query(http_client(&base_url, 5, 60), "/query/status?app=android", "app-key").await;
+    assert_eq!(json["data"]["uid"], 41301);
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn access_key_is_encoded() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (base_url, hits, handle) = start_upstream(200, r#"{"code": 0, "data": {"mid": 41401}}"#).await;
+    let key = "a&b=c+d e";
+    let (_, json) = query(http_client(&base_url, 5, 60), "/query/status", key).await;
+    assert_eq!(json["data"]["uid"], 41401);
+
+    let query_string = hits.lock().unwrap()[0].clone();
+    let params: Vec<(String, String)> = web::Query::<Vec<(String, String)>>::from_query(&query_string)
+        .unwrap()
+        .into_inner();
+    assert_eq!(params[0], ("access_key".to_owned(), key.to_owned()));
+
+    // 签名覆盖编码后的参数
+    let (signed, sign) = query_string.rsplit_once("&sign=").unwrap();
+    assert_eq!(sign, format!("{:x}", md5::compute(format!("{signed}appsec"))));
+    handle.stop(false).await;
+}
@@ -0,0 +1,123 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+use json::object;
+
+/// 每个测试使用单独的数据库
+fn workspace(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("brbs-cli-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    // 没有配置bili.apps 命令行工具不需要
+    let config = object! {
+        database: { url: format!("sqlite://{}/black.db?mode=rwc", dir.display()) }
+    };
+    fs::write(dir.join("config.json"), config.dump()).unwrap();
+
+    dir
+}
+
+fn admin(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_brbs-admin"))
+        .args(args)
+        .env("BRBS_CONFIG", dir.join("config.json"))
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn commands_work_without_migrate() {
+    let dir = workspace("fresh");
+
+    let out = admin(&dir, &["user", "set", "42", "black", "测试"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let out = admin(&dir, &["user", "get", "42"]);
+    assert!(out.status.success());
+    assert!(stdout(&out).contains("status: black"));
+
+    // 之后的migrate仍然生成owner
+    let out = admin(&dir, &["migrate"]);
+    assert!(stdout(&out).contains("Generated owner key"));
+
+    let out = admin(&dir, &["migrate"]);
+    assert!(!stdout(&out).contains("Generated owner key"));
+}
+
+#[test]
+fn unknown_command_prints_usage() {
+    let dir = workspace("usage");
+
+    let out = admin(&dir, &["nothing"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Usage"));
+    assert!(!dir.join("black.db").exists());
+}
+
+#[test]
+fn key_lvl_rules() {
+    let dir = workspace("lvl");
+
+    assert!(admin(&dir, &["keys", "gen", "127", "helper"]).status.success());
+    assert!(admin(&dir, &["keys", "gen", "0", "helper"]).status.success());
+    assert!(admin(&dir, &["keys", "gen", "127", "owner"]).status.success());
+
+    assert!(!admin(&dir, &["keys", "gen", "-1", "helper"]).status.success());
+    assert!(!admin(&dir, &["keys", "gen", "128", "helper"]).status.success());
+    assert!(!admin(&dir, &["keys", "gen", "1", "owner"]).status.success());
+
+    let out = admin(&dir, &["keys", "list"]);
+    assert_eq!(stdout(&out).matches("helper").count(), 2);
+
+    assert!(admin(&dir, &["keys", "revoke", "--role", "helper"]).status.success());
+    assert!(!stdout(&admin(&dir, &["keys", "list"])).contains("helper"));
+
+    // 没有该role的key时不算失败
+    assert!(admin(&dir, &["keys", "revoke", "--role", "helper"]).status.success());
+
+    // 不能按role吊销owner 也不能吊销最后一个owner
+    assert!(!admin(&dir, &["keys", "revoke", "--role", "owner"]).status.success());
+    let owner = stdout(&admin(&dir, &["keys", "list"]))
+        .lines()
+        .find(|l| l.ends_with("owner"))
+        .and_then(|l| l.split_whitespace().nth(1).map(|s| s.to_owned()))
+        .unwrap();
+    assert!(!admin(&dir, &["keys", "revoke", &owner]).status.success());
+}
+
+#[test]
+fn export_and_import() {
+    let dir = workspace("import");
+
+    assert!(admin(&dir, &["user", "set", "1", "black", "a"]).status.success());
+    assert!(admin(&dir, &["user", "set", "2", "white", "b"]).status.success());
+
+    let file = dir.join("users.json");
+    let file = file.to_str().unwrap();
+    assert!(admin(&dir, &["export", file]).status.success());
+
+    let exported = json::parse(&fs::read_to_string(file).unwrap()).unwrap();
+    assert_eq!(exported["users"].len(), 2);
+
+    // 状态相同的跳过 无效条目导致失败退出
+    let mut import = exported.clone();
+    import["users"].push(object! { uid: 3, status: "black", reason: "c" }).unwrap();
+    import["users"].push(object! { uid: "x", status: "black" }).unwrap();
+    fs::write(file, import.dump()).unwrap();
+
+    let out = admin(&dir, &["import", file]);
+    assert!(!out.status.success());
+    assert!(stdout(&out).contains("Imported 1 users, skipped 2 unchanged, 1 failed"));
+
+    let out = admin(&dir, &["user", "history", "3"]);
+    assert!(stdout(&out).contains("by import"));
+}
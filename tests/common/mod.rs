@@ -0,0 +1,86 @@
+// 各测试程序只用到其中一部分
+#![allow(dead_code)]
+
+use std::{env, fs, process, sync::Arc};
+
+use actix_web::{
+    dev::ServiceResponse,
+    test::{self, TestRequest},
+    web::Data,
+    App,
+};
+use brbs_rs::{
+    bili_requests::{BiliClient, FakeBiliClient},
+    configs, db, routing,
+};
+use json::{object, JsonValue};
+use tokio::sync::OnceCell;
+
+lazy_static::lazy_static! {
+    static ref OWNER_KEY: OnceCell<String> = OnceCell::new();
+}
+
+/// 写入临时配置并建表 返回一个owner key 同一个测试程序中只执行一次
+pub async fn prepare(database_url: &str) -> String {
+    OWNER_KEY
+        .get_or_init(|| async {
+            let dir = env::temp_dir().join(format!("brbs-test-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let config = object! {
+                database: { url: database_url },
+                abuse: { enabled: false },
+                // 只写入待投递记录 测试中不启动投递任务
+                webhooks: {
+                    subscriptions: [{ name: "test", url: "http://127.0.0.1:1/hook", secret: "secret" }]
+                },
+                bili: {
+                    apps: [{ name: "android", appKey: "appkey", appSec: "appsec", baseUrl: "http://127.0.0.1:1" }]
+                }
+            };
+
+            let path = dir.join("config.json");
+            fs::write(&path, config.dump()).unwrap();
+            env::set_var("BRBS_CONFIG", &path);
+
+            configs::init().unwrap();
+
+            match db::prepare().await {
+                Some(key) => key,
+                // 共用的数据库中已经有owner
+                None => db::list_admin_keys()
+                    .await
+                    .into_iter()
+                    .find(|k| k.role == db::OWNER_ROLE)
+                    .unwrap()
+                    .key,
+            }
+        })
+        .await
+        .clone()
+}
+
+/// 临时目录中的SQLite数据库
+pub fn sqlite_url() -> String {
+    let dir = env::temp_dir().join(format!("brbs-test-{}", process::id()));
+    format!("sqlite://{}/black.db?mode=rwc", dir.display())
+}
+
+pub fn parse(body: &[u8]) -> JsonValue {
+    json::parse(std::str::from_utf8(body).unwrap()).unwrap()
+}
+
+/// 使用SQLite数据库处理请求 不查询access_key
+pub async fn call(req: TestRequest) -> ServiceResponse {
+    prepare(&sqlite_url()).await;
+
+    let bili: Arc<dyn BiliClient> = Arc::new(FakeBiliClient::new());
+    let app = test::init_service(App::new().app_data(Data::from(bili)).configure(routing::configure)).await;
+
+    test::call_service(&app, req.to_request()).await
+}
+
+pub async fn get_json(path: &str) -> JsonValue {
+    let resp = call(TestRequest::get().uri(path)).await;
+    parse(&test::read_body(resp).await)
+}
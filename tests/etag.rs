@@ -0,0 +1,76 @@
+use actix_web::{
+    http::{header, StatusCode},
+    test::{self, TestRequest},
+};
+
+use brbs_rs::{db, enums::Status};
+
+mod common;
+
+/// 返回HTTP状态码和ETag
+async fn get(path: &str, if_none_match: Option<&str>) -> (StatusCode, String) {
+    let mut req = TestRequest::get().uri(path);
+    if let Some(etag) = if_none_match {
+        req = req.insert_header((header::IF_NONE_MATCH, etag));
+    }
+
+    let resp = common::call(req).await;
+    let status = resp.status();
+    let etag = resp.headers().get(header::ETAG).unwrap().to_str().unwrap().to_owned();
+    assert!(resp.headers().contains_key(header::CACHE_CONTROL));
+
+    let body = test::read_body(resp).await;
+    if status == StatusCode::NOT_MODIFIED {
+        assert!(body.is_empty());
+    }
+
+    (status, etag)
+}
+
+#[actix_web::test]
+async fn not_modified_until_changed() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let uid = 44001;
+    assert!(db::do_op(uid, &Status::Black, "admin", "测试").await);
+
+    for path in [
+        format!("/query/status/uid={uid}"),
+        format!("/query/times/uid={uid}"),
+        format!("/query/full/uid={uid}"),
+    ] {
+        let (status, etag) = get(&path, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, same) = get(&path, Some(&etag)).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED, "{path}");
+        assert_eq!(same, etag);
+
+        // 弱校验和多个ETag
+        assert_eq!(get(&path, Some(&format!("W/{etag}"))).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&path, Some(&format!("\"other\", {etag}"))).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&path, Some("*")).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&path, Some("\"other\"")).await.0, StatusCode::OK);
+    }
+
+    let status_path = format!("/query/status/uid={uid}");
+    let (_, before) = get(&status_path, None).await;
+
+    // 状态变更后旧的ETag失效
+    assert!(db::do_op(uid, &Status::None, "admin", "解除").await);
+
+    let (status, after) = get(&status_path, Some(&before)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(after, before);
+}
+
+#[actix_web::test]
+async fn etag_differs_between_users() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (_, etag) = get("/query/status/uid=44101", None).await;
+    let (status, other) = get("/query/status/uid=44102", Some(&etag)).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(other, etag);
+}
@@ -0,0 +1,92 @@
+use std::env;
+
+use brbs_rs::{
+    db,
+    enums::{DeliveryStatus, RevokeError, Status},
+    utils,
+};
+
+mod common;
+
+// 连接的数据库会被写入测试数据 请使用单独的库 未设置时跳过
+const DATABASE_ENV: &str = "BRBS_TEST_POSTGRES";
+
+// sqlx的连接绑定在创建它的运行时上 所有检查放在同一个测试中
+#[actix_web::test]
+async fn postgres_storage() {
+    let url = match env::var(DATABASE_ENV) {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("{DATABASE_ENV} is not set, skipping");
+            return;
+        }
+    };
+    common::prepare(&url).await;
+
+    // 每次运行使用不同的uid 重复运行时不受之前数据影响
+    let now = utils::current_milliseconds();
+    let uid = now;
+
+    // 状态变更和查询
+    let cursor = db::get_last_reason_id().await;
+    assert!(db::do_op(uid, &Status::Black, "admin", "测试").await);
+    assert!(db::do_op(uid, &Status::None, "admin", "解除").await);
+    assert!(db::do_op(uid, &Status::Black, "admin", "再次").await);
+
+    let user = db::get_user_by_id(uid).await;
+    assert_eq!(user.status, Status::Black);
+    assert_eq!(user.last_reason.as_deref(), Some("再次"));
+
+    let summary = db::get_user_summary(uid).await;
+    assert_eq!(summary.status, Status::Black);
+    assert_eq!(summary.black_times, 2);
+    assert_eq!(summary.last_change, user.last_change);
+
+    // 游标之后的变更按id递增
+    let reasons: Vec<_> = db::get_reasons_after(cursor, 1000)
+        .await
+        .into_iter()
+        .filter(|r| r.uid == uid)
+        .collect();
+    assert_eq!(reasons.len(), 3);
+    assert!(reasons.windows(2).all(|w| w[0].id < w[1].id));
+    assert_eq!(reasons[2].id, user.last_change);
+
+    // 状态变更写入待投递的webhook 取出后租约期间不会再被取出
+    let mut claimed = None;
+    while let Some(delivery) = db::claim_due_webhook(now + 1000, now + 60_000).await {
+        if json::parse(&delivery.payload).unwrap()["uid"] == uid {
+            claimed = Some(delivery);
+            break;
+        }
+    }
+    let mut delivery = claimed.unwrap();
+    assert_eq!(delivery.subscription, "test");
+    assert_eq!(delivery.status, DeliveryStatus::Pending);
+
+    delivery.status = DeliveryStatus::Delivered;
+    delivery.attempts += 1;
+    delivery.updated_at = now;
+    assert!(db::update_webhook(&delivery).await);
+    assert!(db::list_webhooks(Some(&DeliveryStatus::Delivered), 1000)
+        .await
+        .iter()
+        .any(|d| d.id == delivery.id));
+
+    // 至少保留一个owner
+    let key = db::gen_key(1, "test").await.unwrap();
+    let id = db::get_admin_key(&key).await.unwrap().id;
+    assert_eq!(db::revoke_admin_key(id).await, Ok(()));
+    assert_eq!(db::revoke_admin_key(id).await, Err(RevokeError::NotFound));
+
+    let owners: Vec<_> = db::list_admin_keys()
+        .await
+        .into_iter()
+        .filter(|k| k.role == db::OWNER_ROLE)
+        .collect();
+    for owner in &owners[1..] {
+        assert_eq!(db::revoke_admin_key(owner.id).await, Ok(()));
+    }
+    assert_eq!(db::revoke_admin_key(owners[0].id).await, Err(RevokeError::LastOwner));
+    assert_eq!(db::count_owner_keys().await, 1);
+}
@@ -0,0 +1,96 @@
+use std::{future, pin::Pin, time::Duration};
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    http::header,
+    test::{self, TestRequest},
+};
+
+use brbs_rs::{db, enums::Status};
+
+mod common;
+
+/// 读取SSE事件直到收到id为until的变更 返回收到的变更id
+async fn read_until(body: &mut Pin<Box<BoxBody>>, until: i64) -> Vec<i64> {
+    let mut text = String::new();
+    let mut ids = Vec::new();
+
+    while !ids.contains(&until) {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), future::poll_fn(|cx| body.as_mut().poll_next(cx)))
+            .await
+            .expect("no event in 5s")
+            .unwrap()
+            .unwrap();
+        text.push_str(std::str::from_utf8(&chunk).unwrap());
+
+        // 只解析完整的事件
+        while let Some((event, rest)) = text.split_once("\n\n") {
+            if let Some(id) = event.strip_prefix("id: ").and_then(|e| e.lines().next()) {
+                ids.push(id.parse().unwrap());
+            }
+            text = rest.to_owned();
+        }
+    }
+
+    ids
+}
+
+async fn open(req: TestRequest) -> Pin<Box<BoxBody>> {
+    let resp = common::call(req).await;
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/event-stream");
+
+    Box::pin(resp.into_body())
+}
+
+#[actix_web::test]
+async fn resume_from_last_event_id() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let uid = 45001;
+    let mut ids = Vec::new();
+    for status in [Status::Black, Status::None, Status::White] {
+        assert!(db::do_op(uid, &status, "admin", "测试").await);
+        ids.push(db::get_last_reason(uid).await.unwrap().id);
+    }
+
+    // 断线重连时从Last-Event-ID之后继续 优先于since参数
+    let mut body = open(
+        TestRequest::get()
+            .uri("/stream/changes?since=0")
+            .insert_header(("Last-Event-ID", ids[0].to_string())),
+    )
+    .await;
+
+    // 同一测试程序中的其他测试也会产生变更
+    let received = read_until(&mut body, ids[2]).await;
+    assert!(received.contains(&ids[1]));
+    assert!(received.iter().all(|id| *id > ids[0]));
+    assert!(received.windows(2).all(|w| w[0] < w[1]));
+
+    // 连接期间的新变更
+    assert!(db::do_op(uid, &Status::Black, "admin", "再次").await);
+    let id = db::get_last_reason(uid).await.unwrap().id;
+    assert!(read_until(&mut body, id).await.iter().all(|i| *i > ids[2]));
+}
+
+#[actix_web::test]
+async fn resume_from_since() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let uid = 45101;
+    assert!(db::do_op(uid, &Status::Black, "admin", "测试").await);
+    let first = db::get_last_reason(uid).await.unwrap().id;
+    assert!(db::do_op(uid, &Status::None, "admin", "解除").await);
+    let second = db::get_last_reason(uid).await.unwrap().id;
+
+    let mut body = open(TestRequest::get().uri(&format!("/stream/changes?since={first}"))).await;
+    assert!(read_until(&mut body, second).await.iter().all(|id| *id > first));
+}
+
+#[actix_web::test]
+async fn invalid_last_event_id() {
+    let resp = common::call(TestRequest::get().uri("/stream/changes").insert_header(("Last-Event-ID", "abc"))).await;
+    let json = common::parse(&test::read_body(resp).await);
+
+    assert_eq!(json["code"], 400);
+}
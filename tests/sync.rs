@@ -0,0 +1,59 @@
+use brbs_rs::{db, enums::Status};
+use json::JsonValue;
+
+mod common;
+
+fn find(changes: &JsonValue, uid: i64) -> Option<i64> {
+    changes
+        .members()
+        .find(|c| c[0] == uid)
+        .and_then(|c| c[1].as_i64())
+}
+
+// 会修改变更记录的时间 所有检查放在同一个测试中
+#[actix_web::test]
+async fn sync_changes() {
+    common::prepare(&common::sqlite_url()).await;
+
+    let (black, gray, white) = (43001, 43002, 43003);
+    assert!(db::do_op(black, &Status::Black, "admin", "测试").await);
+    assert!(db::do_op(gray, &Status::Gray, "admin", "测试").await);
+
+    // 首次同步返回全量 不包含灰名单
+    let json = common::get_json("/sync").await;
+    let data = &json["data"];
+    assert_eq!(data["full"], true);
+    assert_eq!(find(&data["changes"], black), Some(1));
+    assert_eq!(find(&data["changes"], gray), None);
+
+    // 增量只返回游标之后变更的用户 灰名单按0返回
+    let cursor = data["cursor"].as_i64().unwrap();
+    assert!(db::do_op(white, &Status::White, "admin", "测试").await);
+    assert!(db::do_op(black, &Status::Gray, "admin", "测试").await);
+
+    let json = common::get_json(&format!("/sync?since={cursor}")).await;
+    let data = &json["data"];
+    assert_eq!(data["full"], false);
+    assert_eq!(data["changes"].len(), 2);
+    assert_eq!(find(&data["changes"], white), Some(2));
+    assert_eq!(find(&data["changes"], black), Some(0));
+    assert!(data["cursor"].as_i64().unwrap() > cursor);
+
+    // 来自其他数据库的游标
+    let json = common::get_json(&format!("/sync?since={}", i64::MAX)).await;
+    assert_eq!(json["data"]["full"], true);
+
+    // 游标之后的变更都在很久以前 即使变更很少也返回全量
+    let pool = sqlx::SqlitePool::connect(&common::sqlite_url()).await.unwrap();
+    sqlx::query("UPDATE reasons SET op_time = 0 WHERE id > ?")
+        .bind(cursor)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let json = common::get_json(&format!("/sync?since={cursor}")).await;
+    let data = &json["data"];
+    assert_eq!(data["full"], true);
+    assert_eq!(find(&data["changes"], white), Some(2));
+    assert_eq!(find(&data["changes"], black), None);
+}